@@ -8,4 +8,4 @@ OUTDIR=./solutions
 SOLUTION=$OUTDIR/solution-$(date --iso-8601=seconds).zip
 
 mkdir -p $OUTDIR
-find . -regextype sed -regex "./part.*/.*sol" -exec zip -j $SOLUTION {} \;
+find . -regextype sed -regex "./part.*/.*\.\(sol\|buy\)" -exec zip -j $SOLUTION {} \;
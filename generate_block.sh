@@ -1,5 +1,5 @@
 #!/bin/bash
 
-./gradlew shadowJar
+cargo +nightly build --release
 
-java -jar ./build/libs/icfpc2019.jar --generate "lambda-client/blocks/$1/puzzle.cond"
+./target/release/icfpc2019 generate "lambda-client/blocks/$1/puzzle.cond" "lambda-client/blocks/$1/puzzle.desc"
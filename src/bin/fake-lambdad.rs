@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use icfpc2019::core::Problem as _;
+use icfpc2019::json::Json;
+use icfpc2019::lambda;
+use icfpc2019::miner::{Block, DONE_FILE, PUZZLE_FILE, TASK_FILE};
+use icfpc2019::problem::Problem;
+use icfpc2019::puzzle::Puzzle;
+use icfpc2019::solution::Solution;
+
+#[cfg(test)]
+#[path = "../testing.rs"]
+mod testing;
+
+static BALANCES_FILE: &str = "balances.json";
+static TIMESTAMP_FILE: &str = "timestamp.txt";
+static SUBMISSIONS_FILE: &str = "submissions.log";
+
+/// Our `PublicKey` in `lambda-client/lambda.conf`.
+const TEAM: u32 = 87;
+
+/// Coins earned by an accepted submission when its block closes.
+const BLOCK_REWARD: u64 = 1000;
+
+struct Submission {
+    block: u32,
+    solution: String,
+    puzzle: String,
+    errors: Vec<String>,
+}
+
+/// An offline stand-in for `lambdad.py` and the chain behind it.
+///
+/// Replays canned blocks from a directory laid out like the one `lambdad.py`
+/// writes, one at a time. Submissions are checked locally, and when a block
+/// closes the team gets `BLOCK_REWARD` if one of its submissions for it was
+/// accepted. Opened blocks are saved to the data directory the way
+/// `lambdad.py` does, and submissions are logged there.
+struct Chain {
+    blocks: Vec<Block>,
+    team: u32,
+    data_dir: PathBuf,
+    // timestamp and balances of every opened block, the current one last
+    opened: Vec<(f64, BTreeMap<u32, u64>)>,
+    submissions: Vec<Submission>,
+}
+
+impl Chain {
+    /// Loads the numbered block directories of `blocks_dir`, the balances
+    /// start from the first block's `balances.json` if there is one.
+    fn load(blocks_dir: &Path, data_dir: &Path, team: u32) -> io::Result<Chain> {
+        let mut numbers = vec![];
+        for entry in fs::read_dir(blocks_dir)? {
+            let path = entry?.path();
+            if let Some(number) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.parse::<u32>().ok()) {
+                if path.join(TASK_FILE).exists() {
+                    numbers.push(number);
+                }
+            }
+        }
+        numbers.sort_unstable();
+        let mut blocks = vec![];
+        for number in numbers {
+            let block_dir = blocks_dir.join(number.to_string());
+            blocks.push(Block {
+                number,
+                task: fs::read_to_string(block_dir.join(TASK_FILE))?,
+                puzzle: fs::read_to_string(block_dir.join(PUZZLE_FILE))?,
+            });
+        }
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let first = blocks.first().ok_or_else(|| invalid(format!("no blocks in {}", blocks_dir.display())))?;
+        let balances_path = blocks_dir.join(first.number.to_string()).join(BALANCES_FILE);
+        let mut balances = BTreeMap::new();
+        if balances_path.exists() {
+            let json = Json::parse(&fs::read_to_string(&balances_path)?)
+                .map_err(|e| invalid(format!("{}: malformed JSON {}", balances_path.display(), e)))?;
+            let fields = json.as_object().ok_or_else(|| invalid(format!("{}: not an object", balances_path.display())))?;
+            for (id, balance) in fields {
+                match (id.parse(), balance.as_u64()) {
+                    (Ok(id), Some(balance)) => balances.insert(id, balance),
+                    _ => return Err(invalid(format!("{}: malformed balance of '{}'", balances_path.display(), id))),
+                };
+            }
+        }
+        fs::create_dir_all(data_dir)?;
+        let mut chain = Chain { blocks, team, data_dir: data_dir.to_path_buf(), opened: vec![], submissions: vec![] };
+        chain.open(balances)?;
+        Ok(chain)
+    }
+
+    fn current_block(&self) -> u32 {
+        self.blocks[self.opened.len() - 1].number
+    }
+
+    fn submissions(&self) -> &[Submission] {
+        &self.submissions
+    }
+
+    /// Closes the current block and opens the next one, returns `false` once
+    /// the canned blocks run out.
+    fn advance(&mut self) -> io::Result<bool> {
+        if self.opened.len() == self.blocks.len() {
+            return Ok(false);
+        }
+        let block = self.current_block();
+        let mut balances = self.opened.last().unwrap().1.clone();
+        if self.submissions.iter().any(|s| s.block == block && s.errors.is_empty()) {
+            *balances.entry(self.team).or_insert(0) += BLOCK_REWARD;
+        }
+        self.open(balances)?;
+        Ok(true)
+    }
+
+    fn open(&mut self, balances: BTreeMap<u32, u64>) -> io::Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+        let block = &self.blocks[self.opened.len()];
+        let block_dir = self.data_dir.join(block.number.to_string());
+        fs::create_dir_all(&block_dir)?;
+        fs::write(block_dir.join(TIMESTAMP_FILE), timestamp.to_string())?;
+        fs::write(block_dir.join(BALANCES_FILE), balances_json(&balances).to_string())?;
+        fs::write(block_dir.join(TASK_FILE), &block.task)?;
+        fs::write(block_dir.join(PUZZLE_FILE), &block.puzzle)?;
+        fs::write(block_dir.join(DONE_FILE), "")?;
+        self.opened.push((timestamp, balances));
+        Ok(())
+    }
+
+    /// Answers a JSON-RPC call like `lambdad.py` would.
+    fn handle(&mut self, method: &str, params: &[Json]) -> Result<Json, (i64, String)> {
+        let invalid_params = || (-32602, "Invalid params".to_string());
+        let block = self.current_block();
+        let (block_ts, balances) = self.opened.last().unwrap();
+        match (method, params) {
+            ("getblockchaininfo", []) => Ok(Json::object(vec![
+                ("block", block.into()),
+                ("block_subs", (self.submissions.iter().filter(|s| s.block == block).count() as u32).into()),
+                ("block_ts", Json::Number(*block_ts)),
+                ("total_subs", (self.submissions.len() as u32).into()),
+            ])),
+            ("getmininginfo", []) => {
+                let current = &self.blocks[self.opened.len() - 1];
+                Ok(Json::object(vec![
+                    ("block", block.into()),
+                    ("excluded", Json::Array(vec![])),
+                    ("puzzle", current.puzzle.as_str().into()),
+                    ("task", current.task.as_str().into()),
+                ]))
+            }
+            ("getbalances", []) => Ok(balances_json(balances)),
+            ("getbalance", []) => Ok(Json::Number(balances.get(&self.team).cloned().unwrap_or(0) as f64)),
+            ("getbalance", [id]) => {
+                let id = id.as_u32().ok_or_else(invalid_params)?;
+                Ok(Json::Number(balances.get(&id).cloned().unwrap_or(0) as f64))
+            }
+            ("getblockinfo", []) => Ok(self.block_info(self.opened.len() - 1)),
+            ("getblockinfo", [number]) => {
+                let number = number.as_u32().ok_or_else(invalid_params)?;
+                match self.blocks[..self.opened.len()].iter().position(|b| b.number == number) {
+                    Some(i) => Ok(self.block_info(i)),
+                    None => Err((-32000, format!("block {} doesn't exist yet", number))),
+                }
+            }
+            ("submit", [number, solution, puzzle]) => {
+                let number = number.as_u32().ok_or_else(invalid_params)?;
+                let solution = solution.as_str().ok_or_else(invalid_params)?;
+                let puzzle = puzzle.as_str().ok_or_else(invalid_params)?;
+                if number != block {
+                    return Err((-32000, format!("block {} isn't open for submissions", number)));
+                }
+                let read = |path: &str| fs::read_to_string(path).map_err(|e| (-32000, format!("{}: {}", path, e)));
+                let submission = self.check(number, read(solution)?, read(puzzle)?);
+                let response = Json::object(vec![
+                    ("block", number.into()),
+                    ("errors", Json::Array(submission.errors.iter().map(|e| e.as_str().into()).collect())),
+                ]);
+                self.record(submission).map_err(|e| (-32000, e.to_string()))?;
+                Ok(response)
+            }
+            ("getblockchaininfo", _) | ("getmininginfo", _) | ("getbalances", _) | ("getbalance", _)
+                | ("getblockinfo", _) | ("submit", _) => Err(invalid_params()),
+            _ => Err((-32601, "Method not found".to_string())),
+        }
+    }
+
+    fn block_info(&self, i: usize) -> Json {
+        let (block_ts, balances) = &self.opened[i];
+        Json::object(vec![
+            ("block", self.blocks[i].number.into()),
+            ("block_ts", Json::Number(*block_ts)),
+            ("balances", balances_json(balances)),
+            ("excluded", Json::Array(vec![])),
+            ("puzzle", self.blocks[i].puzzle.as_str().into()),
+            ("task", self.blocks[i].task.as_str().into()),
+        ])
+    }
+
+    fn check(&self, block: u32, solution: String, puzzle: String) -> Submission {
+        let current = &self.blocks[self.opened.len() - 1];
+        let mut errors = vec![];
+        let task = Problem::load_state(current.task.clone().into_bytes()).map_err(|e| format!("malformed task {}", e));
+        let result = task.and_then(|state| {
+            let mut actions = Solution::parse(&solution).map_err(|e| format!("malformed solution {}", e))?;
+            Solution::translate(&mut actions, -state.origin());
+            Problem::score_solution(state, &actions).map_err(|e| format!("task: {}", e))
+        });
+        if let Err(error) = result {
+            errors.push(error);
+        }
+        match Puzzle::parse(&current.puzzle) {
+            Ok(cond) => match Problem::parse_task(&puzzle) {
+                Ok(desc) => errors.extend(cond.check(&desc).into_iter().filter_map(|(constraint, result)| {
+                    result.err().map(|e| format!("puzzle {}: {}", constraint, e))
+                })),
+                Err(error) => errors.push(format!("malformed puzzle solution {}", error)),
+            },
+            Err(error) => errors.push(format!("malformed puzzle {}", error)),
+        }
+        Submission { block, solution, puzzle, errors }
+    }
+
+    fn record(&mut self, submission: Submission) -> io::Result<()> {
+        let line = Json::object(vec![
+            ("block", submission.block.into()),
+            ("solution", submission.solution.as_str().into()),
+            ("puzzle", submission.puzzle.as_str().into()),
+            ("errors", Json::Array(submission.errors.iter().map(|e| e.as_str().into()).collect())),
+        ]);
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(self.data_dir.join(SUBMISSIONS_FILE))?;
+        writeln!(file, "{}", line)?;
+        self.submissions.push(submission);
+        Ok(())
+    }
+}
+
+fn balances_json(balances: &BTreeMap<u32, u64>) -> Json {
+    Json::Object(balances.iter().map(|(id, balance)| (id.to_string(), Json::Number(*balance as f64))).collect())
+}
+
+/// Opens the next block every `interval`, in the background.
+fn advance_every(chain: Arc<Mutex<Chain>>, interval: Duration) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval);
+        let mut locked = chain.lock().unwrap();
+        match locked.advance() {
+            Ok(true) => println!("Opened block {}, {} submissions so far", locked.current_block(), locked.submissions().len()),
+            Ok(false) => break,
+            Err(error) => println!("WARNING: can't open the next block: {}", error),
+        }
+    });
+}
+
+/// Answers JSON-RPC calls on `listener` forever, a thread per connection.
+fn serve(chain: Arc<Mutex<Chain>>, listener: TcpListener) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let chain = chain.clone();
+        std::thread::spawn(move || {
+            let _ = lambda::serve(stream, &mut |method, params| chain.lock().unwrap().handle(method, params));
+        });
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    let usage = "fake-lambdad path/to/canned/blocks path/to/data [host:port] [seconds per block]";
+    let blocks = args.get(1).expect(usage);
+    let data = args.get(2).expect(usage);
+    let address = args.get(3).map(|s| s.as_str()).unwrap_or(lambda::DEFAULT_ADDRESS);
+    let interval = args.get(4).map(|s| s.parse().expect(usage)).unwrap_or(60.0);
+    let chain = Arc::new(Mutex::new(Chain::load(Path::new(blocks), Path::new(data), TEAM)?));
+    advance_every(chain.clone(), Duration::from_secs_f64(interval));
+    println!("Serving block {} on {}", chain.lock().unwrap().current_block(), address);
+    serve(chain, TcpListener::bind(address)?)
+}
+
+#[test]
+fn test_fake_lambdad() {
+    use icfpc2019::lambda::{BlockInfo, Client, GetBalance, GetBlockInfo, GetBlockchainInfo, GetMiningInfo, RpcError, Submit};
+    use icfpc2019::miner::{Miner, Outcome, Source};
+    use crate::testing::TempDir;
+
+    let temp = TempDir::new("fake-lambdad");
+    let dir = temp.path();
+    let (blocks_dir, data_dir) = (dir.join("canned"), dir.join("data"));
+    let task = "(0,0),(6,0),(6,6),(0,6)#(0,0)#(2,2),(4,2),(4,4),(2,4)#B(5,5)";
+    for (number, cond) in [(1, "1,1,10,4,12,1,0,0,0,1,1#(1,1),(8,8)#(5,5)"), (2, "2,1,10,4,12,0,0,0,0,0,0##")].iter() {
+        let block_dir = blocks_dir.join(number.to_string());
+        fs::create_dir_all(&block_dir).unwrap();
+        fs::write(block_dir.join(TASK_FILE), task).unwrap();
+        fs::write(block_dir.join(PUZZLE_FILE), cond).unwrap();
+    }
+    fs::write(blocks_dir.join("1").join(BALANCES_FILE), r#"{"1": 500, "87": 0}"#).unwrap();
+
+    let chain = Arc::new(Mutex::new(Chain::load(&blocks_dir, &data_dir, TEAM).unwrap()));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server_chain = chain.clone();
+    std::thread::spawn(move || serve(server_chain, listener));
+    let client = Client::new(&address);
+    assert_eq!(client.call(&GetMiningInfo).unwrap().block, 1);
+    assert!(data_dir.join("1").join(DONE_FILE).exists());
+
+    // a bad submission is recorded but not rewarded, a good one is
+    let sol = dir.join("bad.sol");
+    fs::write(&sol, "WWW").unwrap();
+    let response = client.call(&Submit { block: 1, solution: sol.clone(), puzzle: blocks_dir.join("1").join(TASK_FILE) }).unwrap();
+    let errors = response.result.get("errors").and_then(Json::as_array).unwrap();
+    assert_eq!(errors.iter().filter_map(Json::as_str).map(|e| e.split(':').next().unwrap()).collect::<Vec<_>>(), vec![
+        "task", "puzzle polygon", "puzzle bounding box", "puzzle area", "puzzle included cells",
+        "puzzle excluded cells", "puzzle booster counts", "puzzle booster placement",
+    ]);
+    let mut miner = Miner::new(Client::new(&address), Source::Daemon, &dir.join("miner")).unwrap();
+    match miner.step().unwrap() {
+        Some((1, Outcome::Submitted(response))) => assert_eq!(response, r#"{"block":1,"errors":[]}"#),
+        result => panic!("unexpected {:?}", result),
+    }
+    assert_eq!(client.call(&GetBlockchainInfo).unwrap().block_subs, 2);
+    assert_eq!(fs::read_to_string(data_dir.join(SUBMISSIONS_FILE)).unwrap().lines().count(), 2);
+
+    assert!(chain.lock().unwrap().advance().unwrap());
+    assert_eq!(client.call(&GetBalance { id: None }).unwrap(), BLOCK_REWARD);
+    assert_eq!(client.call(&GetBalance { id: Some(1) }).unwrap(), 500);
+    let info = client.call(&GetBlockInfo { block: Some(1) }).unwrap();
+    assert_eq!((info.block, info.balances.get(&87)), (1, Some(&0)));
+    match client.call(&Submit { block: 1, solution: sol.clone(), puzzle: sol.clone() }) {
+        Err(RpcError::Server { code: -32000, .. }) => {}
+        result => panic!("unexpected {:?}", result),
+    }
+    match client.call(&GetBlockInfo { block: Some(3) }) {
+        Err(RpcError::Server { code: -32000, .. }) => {}
+        result => panic!("unexpected {:?}", result.map(|BlockInfo { block, .. }| block)),
+    }
+    match client.call_raw("getbalance", vec!["me".into()]) {
+        Err(RpcError::Server { code: -32602, .. }) => {}
+        result => panic!("unexpected {:?}", result),
+    }
+    assert!(!chain.lock().unwrap().advance().unwrap());
+
+    let chain = Arc::new(Mutex::new(Chain::load(&blocks_dir, &dir.join("timed"), TEAM).unwrap()));
+    advance_every(chain.clone(), Duration::from_millis(10));
+    for _ in 0..500 {
+        if chain.lock().unwrap().current_block() == 2 {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(chain.lock().unwrap().current_block(), 2);
+    assert_eq!(chain.lock().unwrap().submissions().len(), 0);
+}
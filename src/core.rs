@@ -1,29 +1,45 @@
 pub trait State {
     type Action;
     type ReverseAction;
+    type ActionError;
 
     fn info(&self) -> String;
     fn complete(&self) -> bool;
     fn agents_count(&self) -> u32;
-    fn can_apply(&self, agent_id: u32, action: Self::Action) -> bool;
-    fn apply(&mut self, agent_id: u32, action: Self::Action) -> Self::ReverseAction;
+    fn can_apply(&self, agent_id: u32, action: Self::Action) -> Result<(), Self::ActionError>;
+    fn apply(&mut self, agent_id: u32, action: Self::Action) -> Result<Self::ReverseAction, Self::ActionError>;
     fn unapply(&mut self, agent_id: u32, reverse_action: Self::ReverseAction) -> Self::Action;
+
+    // A tick is one global time step in which every agent acts at most once, in id order.
+    // Agents without an action (`None` or past the end of `actions`) wait, agents
+    // created during a tick don't act until the next one.
+    type TickReverse;
+
+    fn tick(&self) -> u32;
+    fn apply_tick(&mut self, actions: &[Option<Self::Action>]) -> Result<Self::TickReverse, (u32, Self::ActionError)>;
+    fn unapply_tick(&mut self, reverse: Self::TickReverse) -> Vec<Option<Self::Action>>;
 }
 
 pub trait Problem {
     type State: State;
     type Error;
+    type ParseError;
 
     type StateAction = <Self::State as State>::Action;
 
-    fn load_state(data: Vec<u8>) -> Self::State;
-    fn save_solution(solution: Vec<Self::StateAction>) -> Vec<u8>;
-    fn score_solution(solution: Vec<Self::StateAction>) -> Result<u64, Self::Error>;
+    // Solutions hold the actions of every agent in the coordinates of the loaded state.
+    fn load_state(data: Vec<u8>) -> Result<Self::State, Self::ParseError>;
+    fn save_solution(state: &Self::State, solution: &[Vec<Self::StateAction>]) -> Vec<u8>;
+    fn score_solution(state: Self::State, solution: &[Vec<Self::StateAction>]) -> Result<u32, Self::Error>;
 }
 
 pub trait Strategy {
     type State: State;
 
     fn name() -> String;
-    fn run(state: Self::State, callback: dyn Fn(Vec<<<Self as crate::core::Strategy>::State as State>::Action>) -> ());
+    // Reports the actions of every tick, stops at the first action the state rejects.
+    fn run(
+        state: Self::State,
+        callback: &mut dyn FnMut(Vec<<Self::State as State>::Action>),
+    ) -> Result<(), (u32, <Self::State as State>::ActionError)>;
 }
@@ -8,7 +8,7 @@ pub struct Point2D {
 
 impl Point2D {
     pub fn new(x: i32, y: i32) -> Point2D {
-        return Point2D {
+        Point2D {
             x,
             y,
         }
@@ -0,0 +1,221 @@
+use std::collections::VecDeque;
+
+use crate::core;
+use crate::core::State as _;
+use crate::geometry::Point2D;
+use crate::grid::GridCell;
+use crate::state::{Action, ActionError, BoosterType, State};
+
+static MOVES: [Action; 4] = [Action::MoveLeft, Action::MoveUp, Action::MoveRight, Action::MoveDown];
+
+/// Sends every robot to the nearest free cell nobody else is heading to,
+/// finishing small regions of free cells first.
+///
+/// Boosters in the inventory are used as soon as possible: manipulators are
+/// attached in front, fast wheels and drills are switched on, and robot 0
+/// fetches the C boosters and takes them to a spawn point. Beacons are not
+/// used.
+pub struct Greedy;
+
+impl core::Strategy for Greedy {
+    type State = State;
+
+    fn name() -> String {
+        "Greedy".to_string()
+    }
+
+    fn run(mut state: State, callback: &mut dyn FnMut(Vec<Action>)) -> Result<(), (u32, ActionError)> {
+        let mut routes = Routes::new(&state);
+        while !state.complete() {
+            let mut actions = vec![];
+            for id in 0..state.agents_count() {
+                actions.push(routes.next_action(&state, id, &actions));
+            }
+            let reverse = state.apply_tick(&actions.iter().map(|a| Some(*a)).collect::<Vec<_>>())?;
+            // a robot that cut off a part of a region looks for the smallest one again
+            for (id, reverse_action) in reverse.reverse_actions.iter().enumerate() {
+                if let (Some(path), Some(true)) = (routes.paths.get_mut(id), reverse_action.as_ref().map(|r| r.split_region)) {
+                    path.clear();
+                }
+            }
+            let stuck = actions.iter().all(|a| *a == Action::NoOp);
+            callback(actions);
+            if stuck {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Routes {
+    // remaining steps of every robot, next one last
+    paths: Vec<Vec<Point2D>>,
+    // BFS bookkeeping reused between searches, `visited[i] == generation` marks a visit
+    visited: Vec<u32>,
+    parent: Vec<Point2D>,
+    generation: u32,
+}
+
+impl Routes {
+    fn new(state: &State) -> Routes {
+        let size = state.grid().area();
+        Routes {
+            paths: vec![],
+            visited: vec![0; size],
+            parent: vec![Point2D::new(0, 0); size],
+            generation: 0,
+        }
+    }
+
+    /// The action of robot `id` for this tick, `taken` are the actions of the
+    /// robots before it that use up boosters of the shared inventory.
+    fn next_action(&mut self, state: &State, id: u32, taken: &[Action]) -> Action {
+        let robot = state.robot(id);
+        let inventory = state.inventory();
+        let has = |type_: BoosterType| {
+            inventory.count(type_) as usize > taken.iter().filter(|a| a.booster() == Some(type_)).count()
+        };
+        if has(BoosterType::C) && state.booster_positions(BoosterType::X).contains(&robot.position) {
+            return Action::Clone;
+        }
+        if has(BoosterType::B) {
+            return Action::Attach(robot.next_attachment_point());
+        }
+        if has(BoosterType::F) && robot.fuel_left == 0 {
+            return Action::Accelerate;
+        }
+        if has(BoosterType::L) && robot.drill_left == 0 {
+            return Action::Drill;
+        }
+
+        while self.paths.len() <= id as usize {
+            self.paths.push(vec![]);
+        }
+        let targets = self.paths.iter().enumerate()
+            .filter(|(i, _)| *i != id as usize)
+            .filter_map(|(_, path)| path.first().cloned())
+            .collect::<Vec<_>>();
+        // robot 0 collects the clone boosters on the map and takes them to a spawn point
+        let spawn_points = state.booster_positions(BoosterType::X);
+        let clone_boosters = state.booster_positions(BoosterType::C);
+        let cloning = id == 0 && !spawn_points.is_empty() && (inventory.has(BoosterType::C) || !clone_boosters.is_empty());
+        let clone_targets = if inventory.has(BoosterType::C) { spawn_points } else { clone_boosters };
+
+        let path = &self.paths[id as usize];
+        let still_valid = match (path.first(), path.last()) {
+            (Some(target), Some(next)) => {
+                let target_ok = if cloning { clone_targets.contains(target) } else { state.grid()[*target] == GridCell::Free };
+                target_ok && MOVES.iter().any(|m| self.land(state, id, robot.position, *m, 0) == Some(*next))
+            }
+            _ => false,
+        };
+        if !still_valid {
+            let path = if cloning {
+                self.search(state, id, |p| clone_targets.contains(&p))
+            } else {
+                self.search(state, id, |p| state.grid()[p] == GridCell::Free && !targets.contains(&p))
+            };
+            self.paths[id as usize] = path;
+        }
+
+        match self.paths[id as usize].pop() {
+            Some(next) => *MOVES.iter().find(|m| self.land(state, id, robot.position, **m, 0) == Some(next)).unwrap(),
+            None => Action::NoOp,
+        }
+    }
+
+    /// Where a move from `p` taken `depth` steps into a route ends up, following
+    /// the double step of fast wheels.
+    fn land(&self, state: &State, id: u32, p: Point2D, m: Action, depth: usize) -> Option<Point2D> {
+        let robot = state.robot(id);
+        let passable = |p: Point2D| state.grid().contains(p) && match state.grid()[p] {
+            GridCell::Void => false,
+            // only drill through cells reached well before the drill runs out
+            GridCell::Obstacle => (robot.drill_left as usize) > depth + 1,
+            _ => true,
+        };
+        let d = m.move_point().unwrap();
+        if !passable(p + d) {
+            None
+        } else if (robot.fuel_left as usize) > depth && passable(p + d + d) {
+            Some(p + d + d)
+        } else {
+            Some(p + d)
+        }
+    }
+
+    /// Breadth-first search from the robot position, returns the cells the
+    /// robot lands on to reach the closest one matching `is_target`, target
+    /// first. Among the closest ones, the one in the smallest region wins so
+    /// that pockets get wrapped before the robot moves on.
+    fn search<F: Fn(Point2D) -> bool>(&mut self, state: &State, id: u32, is_target: F) -> Vec<Point2D> {
+        let grid = state.grid();
+        let robot = state.robot(id);
+        let index = |p: Point2D| grid.linear_index(p);
+        let regions = state.regions();
+        let several = regions.count() > 1;
+        let region_size = |p: Point2D| regions.label(grid, p).map_or(0, |l| regions.size(l));
+        self.generation += 1;
+
+        let mut best: Option<(Point2D, usize)> = None;
+        let mut queue = VecDeque::new();
+        queue.push_back((robot.position, 0));
+        self.visited[index(robot.position)] = self.generation;
+        while let Some((p, depth)) = queue.pop_front() {
+            if best.is_some_and(|(_, d)| depth > d) {
+                break;
+            }
+            if p != robot.position && is_target(p) {
+                if best.is_none_or(|(b, _)| region_size(p) < region_size(b)) {
+                    best = Some((p, depth));
+                }
+                if !several {
+                    break;
+                }
+                continue;
+            }
+            for m in MOVES.iter() {
+                if let Some(next) = self.land(state, id, p, *m, depth) {
+                    if self.visited[index(next)] != self.generation {
+                        self.visited[index(next)] = self.generation;
+                        self.parent[index(next)] = p;
+                        queue.push_back((next, depth + 1));
+                    }
+                }
+            }
+        }
+        let mut path = vec![];
+        if let Some((mut q, _)) = best {
+            while q != robot.position {
+                path.push(q);
+                q = self.parent[index(q)];
+            }
+        }
+        path
+    }
+}
+
+#[test]
+fn test_greedy() {
+    use crate::core::Strategy;
+    use crate::problem::Problem;
+    use crate::validator;
+
+    let solve = |desc: &str, purchases: &[BoosterType]| {
+        let mut ticks = vec![];
+        Greedy::run(Problem::parse_with_purchases(desc, purchases).unwrap(), &mut |actions| ticks.push(actions)).unwrap();
+        let mut solution = vec![vec![]; ticks.last().unwrap().len()];
+        for actions in ticks.iter() {
+            for (id, action) in actions.iter().enumerate() {
+                solution[id].push(*action);
+            }
+        }
+        validator::validate(&mut Problem::parse_with_purchases(desc, purchases).unwrap(), &solution)
+    };
+    let desc = include_str!("../part-1-initial/prob-010.desc");
+    let time = solve(desc, &[]).unwrap();
+    assert!(solve(desc, &[BoosterType::B, BoosterType::B]).unwrap() < time);
+    assert!(solve(include_str!("../part-3-clones/prob-230.desc"), &[]).is_ok());
+}
+
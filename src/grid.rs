@@ -1,4 +1,5 @@
 use std::ops;
+use std::rc::Rc;
 
 use crate::geometry::*;
 
@@ -13,17 +14,37 @@ pub enum GridCell {
 
 impl GridCell {
     pub fn is_obstacle(&self) -> bool {
-        match self {
-            GridCell::Obstacle | GridCell::Void => true,
-            _ => false
-        }
+        matches!(self, GridCell::Obstacle | GridCell::Void)
     }
 }
 
+// every row starts on a word boundary so that region queries work on whole words
+const WORD_BITS: u32 = 64;
+
+static CELLS: [GridCell; 4] = [GridCell::Obstacle, GridCell::Void, GridCell::Wrapped, GridCell::Free];
+
+/// Offsets of the side neighbours of a cell.
+pub static NEIGHBOURS_4: [Point2D; 4] = [
+    Point2D { x: -1, y: 0 }, Point2D { x: 0, y: 1 }, Point2D { x: 1, y: 0 }, Point2D { x: 0, y: -1 },
+];
+/// Offsets of the 8 cells around a cell in circular order, sides at even positions.
+pub static NEIGHBOURS_8: [Point2D; 8] = [
+    Point2D { x: -1, y: 0 }, Point2D { x: -1, y: 1 }, Point2D { x: 0, y: 1 }, Point2D { x: 1, y: 1 },
+    Point2D { x: 1, y: 0 }, Point2D { x: 1, y: -1 }, Point2D { x: 0, y: -1 }, Point2D { x: -1, y: -1 },
+];
+
+/// Map cells packed as bitplanes: a cell is Free when passable, Wrapped when
+/// passable and wrapped, and otherwise Void or Obstacle depending on the void
+/// plane. The void plane doesn't change once the map is projected, so clones
+/// share it and only copy the two other planes.
+#[derive(Clone)]
 pub struct Grid {
-    pub width: u16,
-    pub height: u16,
-    grid: Vec<GridCell>,
+    pub width: u32,
+    pub height: u32,
+    stride: u32,
+    passable: Vec<u64>,
+    wrapped: Vec<u64>,
+    void: Rc<Vec<u64>>,
     pub num_obstacles: u32,
     pub num_void: u32,
     pub num_wrapped: u32,
@@ -31,17 +52,30 @@ pub struct Grid {
 }
 
 impl Grid {
-    pub fn new(width: u16, height: u16, initial_value: GridCell) -> Grid {
-        let size = (width * height) as u32;
-        Grid {
+    pub fn new(width: u32, height: u32, initial_value: GridCell) -> Grid {
+        let size = width.checked_mul(height).expect("grid is too large");
+        let stride = width.div_ceil(WORD_BITS);
+        let words = (stride * height) as usize;
+        let mut grid = Grid {
             width,
             height,
-            grid: vec![initial_value; size as usize],
-            num_obstacles: if initial_value == GridCell::Obstacle { size } else { 0 },
-            num_void: if initial_value == GridCell::Void { size } else { 0 },
-            num_wrapped: if initial_value == GridCell::Wrapped { size } else { 0 },
-            num_free: if initial_value == GridCell::Free { size } else { 0 },
+            stride,
+            passable: vec![0; words],
+            wrapped: vec![0; words],
+            void: Rc::new(vec![0; words]),
+            num_obstacles: size,
+            num_void: 0,
+            num_wrapped: 0,
+            num_free: 0,
+        };
+        if initial_value != GridCell::Obstacle {
+            for y in 0..height as i32 {
+                for x in 0..width as i32 {
+                    grid.set(Point2D::new(x, y), initial_value);
+                }
+            }
         }
+        grid
     }
 
     pub fn set(&mut self, p: Point2D, value: GridCell) {
@@ -51,7 +85,18 @@ impl Grid {
             GridCell::Wrapped => self.num_wrapped -= 1,
             GridCell::Free => self.num_free -= 1,
         }
-        self.grid[(p.y * self.width as i32 + p.x) as usize] = value;
+        let (word, mask) = self.bit(p);
+        let (passable, wrapped, void) = match value {
+            GridCell::Obstacle => (false, false, false),
+            GridCell::Void => (false, false, true),
+            GridCell::Wrapped => (true, true, false),
+            GridCell::Free => (true, false, false),
+        };
+        Self::assign(&mut self.passable[word], mask, passable);
+        Self::assign(&mut self.wrapped[word], mask, wrapped);
+        if (self.void[word] & mask != 0) != void {
+            Self::assign(&mut Rc::make_mut(&mut self.void)[word], mask, void);
+        }
         match value {
             GridCell::Obstacle => self.num_obstacles += 1,
             GridCell::Void => self.num_void += 1,
@@ -61,7 +106,119 @@ impl Grid {
     }
 
     pub fn contains(&self, p: Point2D) -> bool {
-        p.x >= 0 && p.x < self.width as i32 && p.y >= 0 && p.y < self.height as i32
+        p.x >= 0 && (p.x as u32) < self.width && p.y >= 0 && (p.y as u32) < self.height
+    }
+
+    pub fn area(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    /// Row-major index of `p` among the `area()` cells, for per-cell data kept
+    /// outside of the grid. Panics outside of the grid.
+    pub fn linear_index(&self, p: Point2D) -> usize {
+        assert!(self.contains(p), "{:?} is outside of the {}x{} grid", p, self.width, self.height);
+        p.y as usize * self.width as usize + p.x as usize
+    }
+
+    /// The cell at a `linear_index`.
+    pub fn point(&self, index: usize) -> Point2D {
+        assert!(index < self.area(), "{} is outside of the {}x{} grid", index, self.width, self.height);
+        Point2D::new((index % self.width as usize) as i32, (index / self.width as usize) as i32)
+    }
+
+    /// Number of Free cells in the rectangle `[bottom_left, top_right)`, clipped to the grid.
+    pub fn count_free(&self, bottom_left: Point2D, top_right: Point2D) -> u32 {
+        self.count(bottom_left, top_right, |word| self.passable[word] & !self.wrapped[word])
+    }
+
+    /// Number of Wrapped cells in the rectangle `[bottom_left, top_right)`, clipped to the grid.
+    pub fn count_wrapped(&self, bottom_left: Point2D, top_right: Point2D) -> u32 {
+        self.count(bottom_left, top_right, |word| self.wrapped[word])
+    }
+
+    /// Free cells in row-major order, skipping a word of 64 cells at a time
+    /// when none of them is free.
+    pub fn free_cells(&self) -> FreeCells<'_> {
+        FreeCells { grid: self, word: 0, bits: 0 }
+    }
+
+    /// Every cell with its position, row by row from the bottom.
+    pub fn cells(&self) -> impl Iterator<Item = (Point2D, GridCell)> + '_ {
+        self.view(Point2D::new(0, 0), Point2D::new(self.width as i32, self.height as i32)).cells()
+    }
+
+    pub fn cells_of(&self, kind: GridCell) -> impl Iterator<Item = Point2D> + '_ {
+        self.cells().filter(move |(_, cell)| *cell == kind).map(|(p, _)| p)
+    }
+
+    /// Side neighbours of `p` a robot can stand on.
+    pub fn neighbours_4(&self, p: Point2D) -> impl Iterator<Item = Point2D> + '_ {
+        self.passable_around(p, &NEIGHBOURS_4)
+    }
+
+    /// Side and corner neighbours of `p` a robot can stand on.
+    pub fn neighbours_8(&self, p: Point2D) -> impl Iterator<Item = Point2D> + '_ {
+        self.passable_around(p, &NEIGHBOURS_8)
+    }
+
+    pub fn row(&self, y: i32) -> impl Iterator<Item = GridCell> + '_ {
+        (0..self.width as i32).map(move |x| self[Point2D::new(x, y)])
+    }
+
+    pub fn column(&self, x: i32) -> impl Iterator<Item = GridCell> + '_ {
+        (0..self.height as i32).map(move |y| self[Point2D::new(x, y)])
+    }
+
+    /// The rectangle `[bottom_left, top_right)` clipped to the grid.
+    pub fn view(&self, bottom_left: Point2D, top_right: Point2D) -> GridView<'_> {
+        let min = Point2D::new(bottom_left.x.max(0), bottom_left.y.max(0));
+        let max = Point2D::new(top_right.x.min(self.width as i32), top_right.y.min(self.height as i32));
+        GridView {
+            grid: self,
+            origin: min,
+            width: (max.x - min.x).max(0) as u32,
+            height: (max.y - min.y).max(0) as u32,
+        }
+    }
+
+    fn passable_around<'a>(&'a self, p: Point2D, offsets: &'a [Point2D]) -> impl Iterator<Item = Point2D> + 'a {
+        offsets.iter()
+            .map(move |d| p + *d)
+            .filter(move |q| self.contains(*q) && !self[*q].is_obstacle())
+    }
+
+    fn bit(&self, p: Point2D) -> (usize, u64) {
+        assert!(self.contains(p), "{:?} is outside of the {}x{} grid", p, self.width, self.height);
+        let word = p.y as u32 * self.stride + p.x as u32 / WORD_BITS;
+        (word as usize, 1 << (p.x as u32 % WORD_BITS))
+    }
+
+    fn assign(word: &mut u64, mask: u64, value: bool) {
+        if value {
+            *word |= mask;
+        } else {
+            *word &= !mask;
+        }
+    }
+
+    fn count<F: Fn(usize) -> u64>(&self, bottom_left: Point2D, top_right: Point2D, cells: F) -> u32 {
+        let min_x = bottom_left.x.max(0) as u32;
+        let min_y = bottom_left.y.max(0) as u32;
+        let max_x = (top_right.x.max(0) as u32).min(self.width);
+        let max_y = (top_right.y.max(0) as u32).min(self.height);
+        if min_x >= max_x || min_y >= max_y {
+            return 0;
+        }
+        let mut result = 0;
+        for y in min_y..max_y {
+            for w in min_x / WORD_BITS..=(max_x - 1) / WORD_BITS {
+                let from = min_x.saturating_sub(w * WORD_BITS);
+                let to = (max_x - w * WORD_BITS).min(WORD_BITS);
+                let mask = (u64::MAX >> (WORD_BITS - (to - from))) << from;
+                result += (cells((y * self.stride + w) as usize) & mask).count_ones();
+            }
+        }
+        result
     }
 }
 
@@ -69,7 +226,82 @@ impl ops::Index<Point2D> for Grid {
     type Output = GridCell;
 
     fn index(&self, p: Point2D) -> &Self::Output {
-        &self.grid[(p.y * self.width as i32 + p.x) as usize]
+        let (word, mask) = self.bit(p);
+        let cell = if self.passable[word] & mask == 0 {
+            if self.void[word] & mask == 0 { GridCell::Obstacle } else { GridCell::Void }
+        } else if self.wrapped[word] & mask == 0 {
+            GridCell::Free
+        } else {
+            GridCell::Wrapped
+        };
+        &CELLS[cell as usize]
+    }
+}
+
+/// A rectangle of a grid addressed relative to its bottom left corner.
+pub struct GridView<'a> {
+    grid: &'a Grid,
+    pub origin: Point2D,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl<'a> GridView<'a> {
+    pub fn contains(&self, p: Point2D) -> bool {
+        p.x >= 0 && (p.x as u32) < self.width && p.y >= 0 && (p.y as u32) < self.height
+    }
+
+    /// Every cell of the view with its position in grid coordinates.
+    pub fn cells(&self) -> impl Iterator<Item = (Point2D, GridCell)> + 'a {
+        let (grid, origin, width) = (self.grid, self.origin, self.width as i32);
+        (0..self.height as i32)
+            .flat_map(move |y| (0..width).map(move |x| origin + Point2D::new(x, y)))
+            .map(move |p| (p, grid[p]))
+    }
+
+    pub fn count_free(&self) -> u32 {
+        self.grid.count_free(self.origin, self.origin + Point2D::new(self.width as i32, self.height as i32))
+    }
+
+    pub fn count_wrapped(&self) -> u32 {
+        self.grid.count_wrapped(self.origin, self.origin + Point2D::new(self.width as i32, self.height as i32))
+    }
+}
+
+impl<'a> ops::Index<Point2D> for GridView<'a> {
+    type Output = GridCell;
+
+    fn index(&self, p: Point2D) -> &Self::Output {
+        assert!(self.contains(p), "{:?} is outside of the {}x{} view", p, self.width, self.height);
+        &self.grid[self.origin + p]
+    }
+}
+
+pub struct FreeCells<'a> {
+    grid: &'a Grid,
+    word: usize,
+    // free cells of the current word not visited yet
+    bits: u64,
+}
+
+impl<'a> Iterator for FreeCells<'a> {
+    type Item = Point2D;
+
+    fn next(&mut self) -> Option<Point2D> {
+        while self.bits == 0 {
+            self.word += 1;
+            if self.word > self.grid.passable.len() {
+                return None;
+            }
+            let w = self.word - 1;
+            self.bits = self.grid.passable[w] & !self.grid.wrapped[w];
+        }
+        let w = (self.word - 1) as u32;
+        let bit = self.bits.trailing_zeros();
+        self.bits &= self.bits - 1;
+        let y = w / self.grid.stride;
+        let x = (w % self.grid.stride) * WORD_BITS + bit;
+        Some(Point2D::new(x as i32, y as i32))
     }
 }
 
@@ -82,12 +314,12 @@ fn test_grid() {
     assert_eq!(grid.num_void, 0);
     assert_eq!(grid.num_wrapped, 0);
     assert_eq!(grid.num_free, 0);
-    assert_eq!(grid.contains(Point2D::new(0, 0)), true);
-    assert_eq!(grid.contains(Point2D::new(-1, 0)), false);
-    assert_eq!(grid.contains(Point2D::new(0, -1)), false);
-    assert_eq!(grid.contains(Point2D::new(1, 1)), true);
-    assert_eq!(grid.contains(Point2D::new(2, 1)), false);
-    assert_eq!(grid.contains(Point2D::new(1, 2)), false);
+    assert!(grid.contains(Point2D::new(0, 0)));
+    assert!(!grid.contains(Point2D::new(-1, 0)));
+    assert!(!grid.contains(Point2D::new(0, -1)));
+    assert!(grid.contains(Point2D::new(1, 1)));
+    assert!(!grid.contains(Point2D::new(2, 1)));
+    assert!(!grid.contains(Point2D::new(1, 2)));
     grid.set(Point2D::new(0, 1), GridCell::Void);
     grid.set(Point2D::new(1, 0), GridCell::Wrapped);
     grid.set(Point2D::new(1, 1), GridCell::Free);
@@ -99,4 +331,87 @@ fn test_grid() {
     assert_eq!(grid.num_void, 1);
     assert_eq!(grid.num_wrapped, 1);
     assert_eq!(grid.num_free, 1);
+    assert_eq!(grid.linear_index(Point2D::new(1, 1)), 3);
+
+    let mut grid = Grid::new(400, 400, GridCell::Void);
+    assert_eq!(grid.area(), 160000);
+    assert_eq!(grid.num_void, 160000);
+    grid.set(Point2D::new(399, 399), GridCell::Free);
+    assert_eq!(grid.linear_index(Point2D::new(399, 399)), 159999);
+    assert_eq!(grid.point(159999), Point2D::new(399, 399));
+    assert_eq!(grid[Point2D::new(399, 399)], GridCell::Free);
+    assert!(!grid.contains(Point2D::new(400, 0)));
+}
+
+#[test]
+fn test_bitplanes() {
+    let mut grid = Grid::new(70, 3, GridCell::Free);
+    for x in 60..70 {
+        grid.set(Point2D::new(x, 1), GridCell::Wrapped);
+    }
+    grid.set(Point2D::new(0, 0), GridCell::Obstacle);
+    grid.set(Point2D::new(69, 2), GridCell::Void);
+    assert_eq!(grid.count_free(Point2D::new(0, 0), Point2D::new(70, 3)), grid.num_free);
+    assert_eq!(grid.count_free(Point2D::new(-5, -5), Point2D::new(100, 100)), 198);
+    assert_eq!(grid.count_free(Point2D::new(50, 1), Point2D::new(70, 2)), 10);
+    assert_eq!(grid.count_wrapped(Point2D::new(63, 0), Point2D::new(66, 3)), 3);
+    assert_eq!(grid.count_wrapped(Point2D::new(5, 5), Point2D::new(1, 1)), 0);
+
+    let free = grid.free_cells().collect::<Vec<_>>();
+    assert_eq!(free.len(), grid.num_free as usize);
+    assert_eq!(free[0], Point2D::new(1, 0));
+    assert_eq!(free[free.len() - 1], Point2D::new(68, 2));
+    assert!(free.iter().all(|p| grid[*p] == GridCell::Free));
+
+    let mut clone = grid.clone();
+    clone.set(Point2D::new(1, 0), GridCell::Wrapped);
+    clone.set(Point2D::new(2, 0), GridCell::Void);
+    assert_eq!(grid[Point2D::new(1, 0)], GridCell::Free);
+    assert_eq!(grid[Point2D::new(2, 0)], GridCell::Free);
+    assert_eq!(clone[Point2D::new(2, 0)], GridCell::Void);
+    assert_eq!(clone[Point2D::new(69, 2)], GridCell::Void);
+    assert_eq!(clone.num_free, grid.num_free - 2);
+}
+
+#[test]
+fn test_grid_queries() {
+    let mut grid = Grid::new(4, 3, GridCell::Free);
+    grid.set(Point2D::new(1, 1), GridCell::Obstacle);
+    grid.set(Point2D::new(3, 2), GridCell::Void);
+    grid.set(Point2D::new(2, 1), GridCell::Wrapped);
+
+    assert_eq!(grid.cells().count(), 12);
+    assert_eq!(grid.cells().next(), Some((Point2D::new(0, 0), GridCell::Free)));
+    assert_eq!(grid.cells_of(GridCell::Wrapped).collect::<Vec<_>>(), vec![Point2D::new(2, 1)]);
+    assert_eq!(grid.cells_of(GridCell::Free).count(), grid.free_cells().count());
+
+    assert_eq!(
+        grid.neighbours_4(Point2D::new(2, 2)).collect::<Vec<_>>(),
+        vec![Point2D::new(1, 2), Point2D::new(2, 1)]
+    );
+    assert_eq!(grid.neighbours_4(Point2D::new(0, 0)).count(), 2);
+    assert_eq!(
+        grid.neighbours_8(Point2D::new(0, 2)).collect::<Vec<_>>(),
+        vec![Point2D::new(1, 2), Point2D::new(0, 1)]
+    );
+    assert_eq!(grid.neighbours_8(Point2D::new(2, 0)).count(), 4);
+
+    assert_eq!(grid.row(1).collect::<Vec<_>>(), vec![GridCell::Free, GridCell::Obstacle, GridCell::Wrapped, GridCell::Free]);
+    assert_eq!(grid.column(3).collect::<Vec<_>>(), vec![GridCell::Free, GridCell::Free, GridCell::Void]);
+
+    let view = grid.view(Point2D::new(1, 1), Point2D::new(10, 10));
+    assert_eq!((view.width, view.height), (3, 2));
+    assert_eq!(view[Point2D::new(0, 0)], GridCell::Obstacle);
+    assert_eq!(view[Point2D::new(2, 1)], GridCell::Void);
+    assert!(!view.contains(Point2D::new(3, 0)));
+    assert_eq!(view.cells().map(|(p, _)| p).last(), Some(Point2D::new(3, 2)));
+    assert_eq!((view.count_free(), view.count_wrapped()), (3, 1));
+    assert_eq!(grid.view(Point2D::new(3, 3), Point2D::new(1, 1)).cells().count(), 0);
+}
+
+#[test]
+#[should_panic(expected = "outside of the 2x2 grid")]
+fn test_grid_bounds() {
+    let grid = Grid::new(2, 2, GridCell::Free);
+    let _ = grid[Point2D::new(-1, 1)];
 }
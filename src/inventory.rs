@@ -0,0 +1,112 @@
+use crate::state::BoosterType;
+
+/// Boosters shared by all robots.
+///
+/// Boosters picked up during a tick are pending until `promote` is called at
+/// the end of it; purchased boosters and the ones collected on the way of a
+/// fast-wheels double move are usable right away.
+#[derive(PartialEq, Eq, Clone, Default, Debug)]
+pub struct Inventory {
+    usable: [u16; 6],
+    pending: Vec<BoosterType>,
+}
+
+impl Inventory {
+    pub fn new() -> Inventory {
+        Inventory::default()
+    }
+
+    pub fn purchased(boosters: &[BoosterType]) -> Inventory {
+        let mut inventory = Inventory::new();
+        for type_ in boosters {
+            inventory.buy(*type_);
+        }
+        inventory
+    }
+
+    pub fn count(&self, type_: BoosterType) -> u16 {
+        self.usable[type_ as usize]
+    }
+
+    pub fn has(&self, type_: BoosterType) -> bool {
+        self.count(type_) > 0
+    }
+
+    pub fn pending(&self) -> &[BoosterType] {
+        &self.pending
+    }
+
+    pub fn buy(&mut self, type_: BoosterType) {
+        assert_ne!(type_, BoosterType::X, "spawn points can't be collected");
+        self.usable[type_ as usize] += 1;
+    }
+
+    /// Consumes a usable booster, `restore` undoes it.
+    pub fn take(&mut self, type_: BoosterType) {
+        assert!(self.has(type_), "no {:?} booster in inventory", type_);
+        self.usable[type_ as usize] -= 1;
+    }
+
+    pub fn restore(&mut self, type_: BoosterType) {
+        self.usable[type_ as usize] += 1;
+    }
+
+    /// Adds a booster that is usable right away, `take` undoes it.
+    pub fn collect(&mut self, type_: BoosterType) {
+        self.buy(type_);
+    }
+
+    /// Adds a booster picked up this tick, `drop_pending` undoes it.
+    pub fn pick_up(&mut self, type_: BoosterType) {
+        assert_ne!(type_, BoosterType::X, "spawn points can't be collected");
+        self.pending.push(type_);
+    }
+
+    pub fn drop_pending(&mut self, type_: BoosterType) {
+        let i = self.pending.iter().rposition(|t| *t == type_).expect("booster was not picked up");
+        self.pending.remove(i);
+    }
+
+    /// Makes the pending boosters usable and returns them so that `demote` can undo it.
+    pub fn promote(&mut self) -> Vec<BoosterType> {
+        let promoted = std::mem::take(&mut self.pending);
+        for type_ in promoted.iter() {
+            self.usable[*type_ as usize] += 1;
+        }
+        promoted
+    }
+
+    pub fn demote(&mut self, promoted: Vec<BoosterType>) {
+        for type_ in promoted.iter() {
+            self.take(*type_);
+        }
+        self.pending.extend(promoted);
+    }
+}
+
+#[test]
+fn test_inventory() {
+    let mut inventory = Inventory::purchased(&[BoosterType::B, BoosterType::B, BoosterType::F]);
+    assert_eq!(inventory.count(BoosterType::B), 2);
+    assert!(inventory.has(BoosterType::F));
+    assert!(!inventory.has(BoosterType::C));
+
+    inventory.take(BoosterType::F);
+    assert!(!inventory.has(BoosterType::F));
+    inventory.restore(BoosterType::F);
+    assert!(inventory.has(BoosterType::F));
+
+    inventory.pick_up(BoosterType::C);
+    inventory.pick_up(BoosterType::L);
+    inventory.pick_up(BoosterType::C);
+    assert!(!inventory.has(BoosterType::C));
+    inventory.drop_pending(BoosterType::L);
+    assert_eq!(inventory.pending(), &[BoosterType::C, BoosterType::C]);
+
+    let before = inventory.clone();
+    let promoted = inventory.promote();
+    assert_eq!(inventory.count(BoosterType::C), 2);
+    assert!(inventory.pending().is_empty());
+    inventory.demote(promoted);
+    assert_eq!(inventory, before);
+}
@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::solution::{ParseError, Parser};
+
+/// A JSON value, just enough for talking to the lambda daemon.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    pub fn parse(s: &str) -> Result<Json, ParseError> {
+        let mut parser = Parser::new(s);
+        let value = value(&mut parser)?;
+        skip_whitespace(&mut parser);
+        match parser.peek() {
+            None => Ok(value),
+            Some(_) => Err(parser.error_at(parser.offset, "expected the end of the document".to_string())),
+        }
+    }
+
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Accepts integral numbers only, `3.0` included.
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_f64()
+            .filter(|n| n.fract() == 0.0 && *n >= 0.0 && *n <= u64::MAX as f64)
+            .map(|n| n as u64)
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        self.as_u64().and_then(|n| u32::try_from(n).ok())
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&BTreeMap<String, Json>> {
+        match self {
+            Json::Object(fields) => Some(fields),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<u32> for Json {
+    fn from(n: u32) -> Json {
+        Json::Number(n.into())
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if !n.is_finite() => write!(f, "null"),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+fn skip_whitespace(parser: &mut Parser) {
+    while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = parser.peek() {
+        parser.next_byte();
+    }
+}
+
+fn value(parser: &mut Parser) -> Result<Json, ParseError> {
+    skip_whitespace(parser);
+    match parser.peek() {
+        Some(b'{') => {
+            parser.next_byte();
+            let mut fields = BTreeMap::new();
+            skip_whitespace(parser);
+            if parser.peek() == Some(b'}') {
+                parser.next_byte();
+                return Ok(Json::Object(fields));
+            }
+            loop {
+                skip_whitespace(parser);
+                let key = string(parser)?;
+                skip_whitespace(parser);
+                parser.expect(b':')?;
+                fields.insert(key, value(parser)?);
+                skip_whitespace(parser);
+                match parser.next_byte() {
+                    Some(b',') => continue,
+                    Some(b'}') => return Ok(Json::Object(fields)),
+                    _ => return Err(parser.error_at(parser.offset.saturating_sub(1), "expected ',' or '}'".to_string())),
+                }
+            }
+        }
+        Some(b'[') => {
+            parser.next_byte();
+            let mut items = vec![];
+            skip_whitespace(parser);
+            if parser.peek() == Some(b']') {
+                parser.next_byte();
+                return Ok(Json::Array(items));
+            }
+            loop {
+                items.push(value(parser)?);
+                skip_whitespace(parser);
+                match parser.next_byte() {
+                    Some(b',') => continue,
+                    Some(b']') => return Ok(Json::Array(items)),
+                    _ => return Err(parser.error_at(parser.offset.saturating_sub(1), "expected ',' or ']'".to_string())),
+                }
+            }
+        }
+        Some(b'"') => string(parser).map(Json::String),
+        Some(b'-') | Some(b'0'..=b'9') => number(parser),
+        Some(b't') => keyword(parser, "true", Json::Bool(true)),
+        Some(b'f') => keyword(parser, "false", Json::Bool(false)),
+        Some(b'n') => keyword(parser, "null", Json::Null),
+        _ => Err(parser.error_at(parser.offset, "expected a value".to_string())),
+    }
+}
+
+fn keyword(parser: &mut Parser, word: &str, value: Json) -> Result<Json, ParseError> {
+    let start = parser.offset;
+    for &c in word.as_bytes() {
+        if parser.next_byte() != Some(c) {
+            return Err(parser.error_at(start, format!("expected '{}'", word)));
+        }
+    }
+    Ok(value)
+}
+
+fn number(parser: &mut Parser) -> Result<Json, ParseError> {
+    let start = parser.offset;
+    let mut s = String::new();
+    while let Some(c @ (b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E')) = parser.peek() {
+        s.push(c as char);
+        parser.next_byte();
+    }
+    s.parse::<f64>()
+        .map(Json::Number)
+        .map_err(|_| parser.error_at(start, "expected a number".to_string()))
+}
+
+fn string(parser: &mut Parser) -> Result<String, ParseError> {
+    let start = parser.offset;
+    parser.expect(b'"')?;
+    let mut bytes = vec![];
+    loop {
+        match parser.next_byte() {
+            Some(b'"') => break,
+            Some(b'\\') => {
+                let c = match parser.next_byte() {
+                    Some(b'"') => '"',
+                    Some(b'\\') => '\\',
+                    Some(b'/') => '/',
+                    Some(b'b') => '\u{8}',
+                    Some(b'f') => '\u{c}',
+                    Some(b'n') => '\n',
+                    Some(b'r') => '\r',
+                    Some(b't') => '\t',
+                    Some(b'u') => {
+                        let high = hex4(parser)?;
+                        let code = if (0xd800..0xdc00).contains(&high) && parser.peek() == Some(b'\\') {
+                            parser.next_byte();
+                            parser.expect(b'u')?;
+                            match hex4(parser)? {
+                                low @ 0xdc00..=0xdfff => 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00),
+                                _ => high,
+                            }
+                        } else {
+                            high
+                        };
+                        char::from_u32(code)
+                            .ok_or_else(|| parser.error_at(parser.offset - 4, "invalid unicode escape".to_string()))?
+                    }
+                    _ => return Err(parser.error_at(parser.offset.saturating_sub(1), "invalid escape".to_string())),
+                };
+                bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+            Some(c) => bytes.push(c),
+            None => return Err(parser.error_at(start, "unterminated string".to_string())),
+        }
+    }
+    // The input is a &str and escapes are re-encoded, so this can't fail.
+    Ok(String::from_utf8(bytes).unwrap())
+}
+
+fn hex4(parser: &mut Parser) -> Result<u32, ParseError> {
+    let start = parser.offset;
+    let mut code = 0;
+    for _ in 0..4 {
+        let digit = parser.next_byte()
+            .and_then(|c| (c as char).to_digit(16))
+            .ok_or_else(|| parser.error_at(start, "expected 4 hex digits".to_string()))?;
+        code = code * 16 + digit;
+    }
+    Ok(code)
+}
+
+#[test]
+fn test_json() {
+    let s = r#" {"block": 12, "ts": 1561.5, "ok": true, "none": null,
+                 "list": [1, -2e3, "a\"b\\c\u00e9\ud83d\ude00"], "empty": {}, "e": []} "#;
+    let json = Json::parse(s).unwrap();
+    assert_eq!(json.get("block").and_then(Json::as_u32), Some(12));
+    assert_eq!(json.get("ts").and_then(Json::as_f64), Some(1561.5));
+    assert_eq!(json.get("ts").and_then(Json::as_u64), None);
+    assert_eq!(json.get("ok"), Some(&Json::Bool(true)));
+    assert_eq!(json.get("none"), Some(&Json::Null));
+    let list = json.get("list").and_then(Json::as_array).unwrap();
+    assert_eq!(list[1], Json::Number(-2000.0));
+    assert_eq!(list[2].as_str(), Some("a\"b\\c\u{e9}\u{1f600}"));
+    assert_eq!(
+        json.to_string(),
+        r#"{"block":12,"e":[],"empty":{},"list":[1,-2000,"a\"b\\cé😀"],"none":null,"ok":true,"ts":1561.5}"#
+    );
+    assert_eq!(Json::parse(&json.to_string()), Ok(json));
+    assert_eq!(Json::object(vec![("s", "\n\u{1}".into())]).to_string(), r#"{"s":"\n\u0001"}"#);
+
+    assert_eq!(Json::parse("[1,]").unwrap_err().offset, 3);
+    assert_eq!(Json::parse("{\"a\" 1}").unwrap_err().offset, 5);
+    assert_eq!(Json::parse("\"abc").unwrap_err().offset, 0);
+    assert_eq!(Json::parse("tru").unwrap_err().offset, 0);
+    assert_eq!(Json::parse("1 2").unwrap_err().offset, 2);
+    assert_eq!(Json::parse("\"\\q\"").unwrap_err().offset, 2);
+}
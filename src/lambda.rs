@@ -0,0 +1,419 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::json::Json;
+use crate::solution::ParseError;
+
+/// Where `lambdad.py` listens unless `lambda.conf` says otherwise.
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:8332";
+
+#[derive(Debug)]
+pub enum RpcError {
+    Io(io::Error),
+    Http(String),
+    Json(ParseError),
+    Server { code: i64, message: String },
+    UnexpectedResult { method: &'static str, result: Json },
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RpcError::Io(error) => write!(f, "{}", error),
+            RpcError::Http(message) => write!(f, "bad HTTP response: {}", message),
+            RpcError::Json(error) => write!(f, "malformed JSON {}", error),
+            RpcError::Server { code, message } => write!(f, "daemon error {}: {}", code, message),
+            RpcError::UnexpectedResult { method, result } => write!(f, "unexpected {} result {}", method, result),
+        }
+    }
+}
+
+impl From<io::Error> for RpcError {
+    fn from(error: io::Error) -> RpcError {
+        RpcError::Io(error)
+    }
+}
+
+/// A JSON-RPC method of the daemon with its typed result.
+pub trait Method {
+    type Response;
+
+    fn name(&self) -> &'static str;
+    fn params(&self) -> Vec<Json>;
+    fn response(result: &Json) -> Option<Self::Response>;
+}
+
+pub struct GetBlockchainInfo;
+
+#[derive(PartialEq, Debug)]
+pub struct BlockchainInfo {
+    pub block: u32,
+    pub block_subs: u32,
+    pub block_ts: f64,
+    pub total_subs: u32,
+}
+
+impl Method for GetBlockchainInfo {
+    type Response = BlockchainInfo;
+
+    fn name(&self) -> &'static str { "getblockchaininfo" }
+    fn params(&self) -> Vec<Json> { vec![] }
+
+    fn response(result: &Json) -> Option<BlockchainInfo> {
+        Some(BlockchainInfo {
+            block: result.get("block")?.as_u32()?,
+            block_subs: result.get("block_subs")?.as_u32()?,
+            block_ts: result.get("block_ts")?.as_f64()?,
+            total_subs: result.get("total_subs")?.as_u32()?,
+        })
+    }
+}
+
+pub struct GetMiningInfo;
+
+#[derive(PartialEq, Debug)]
+pub struct MiningInfo {
+    pub block: u32,
+    pub excluded: Vec<u32>,
+    pub puzzle: String,
+    pub task: String,
+}
+
+impl Method for GetMiningInfo {
+    type Response = MiningInfo;
+
+    fn name(&self) -> &'static str { "getmininginfo" }
+    fn params(&self) -> Vec<Json> { vec![] }
+
+    fn response(result: &Json) -> Option<MiningInfo> {
+        Some(MiningInfo {
+            block: result.get("block")?.as_u32()?,
+            excluded: teams(result.get("excluded")?)?,
+            puzzle: result.get("puzzle")?.as_str()?.to_string(),
+            task: result.get("task")?.as_str()?.to_string(),
+        })
+    }
+}
+
+/// Balances of the current block by team id.
+pub struct GetBalances;
+
+impl Method for GetBalances {
+    type Response = BTreeMap<u32, u64>;
+
+    fn name(&self) -> &'static str { "getbalances" }
+    fn params(&self) -> Vec<Json> { vec![] }
+
+    fn response(result: &Json) -> Option<BTreeMap<u32, u64>> {
+        balances(result)
+    }
+}
+
+/// The balance of a team, of our own `PublicKey` when `id` is `None`.
+pub struct GetBalance {
+    pub id: Option<u32>,
+}
+
+impl Method for GetBalance {
+    type Response = u64;
+
+    fn name(&self) -> &'static str { "getbalance" }
+    fn params(&self) -> Vec<Json> { self.id.into_iter().map(Json::from).collect() }
+
+    fn response(result: &Json) -> Option<u64> {
+        result.as_u64()
+    }
+}
+
+/// A past block, or the current one when `block` is `None`.
+pub struct GetBlockInfo {
+    pub block: Option<u32>,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct BlockInfo {
+    pub block: u32,
+    pub block_ts: f64,
+    pub balances: BTreeMap<u32, u64>,
+    pub excluded: Vec<u32>,
+    pub puzzle: String,
+    pub task: String,
+}
+
+impl Method for GetBlockInfo {
+    type Response = BlockInfo;
+
+    fn name(&self) -> &'static str { "getblockinfo" }
+    fn params(&self) -> Vec<Json> { self.block.into_iter().map(Json::from).collect() }
+
+    fn response(result: &Json) -> Option<BlockInfo> {
+        Some(BlockInfo {
+            block: result.get("block")?.as_u32()?,
+            block_ts: result.get("block_ts")?.as_f64()?,
+            balances: balances(result.get("balances")?)?,
+            excluded: teams(result.get("excluded")?)?,
+            puzzle: result.get("puzzle")?.as_str()?.to_string(),
+            task: result.get("task")?.as_str()?.to_string(),
+        })
+    }
+}
+
+/// Submits a `.sol` for the block task and a `.desc` for its puzzle. The paths
+/// are opened by the daemon, so they should be absolute.
+pub struct Submit {
+    pub block: u32,
+    pub solution: PathBuf,
+    pub puzzle: PathBuf,
+}
+
+/// The chain's reply to a submission, passed through by the daemon as is.
+#[derive(PartialEq, Debug)]
+pub struct SubmitResponse {
+    pub result: Json,
+}
+
+impl Method for Submit {
+    type Response = SubmitResponse;
+
+    fn name(&self) -> &'static str { "submit" }
+
+    fn params(&self) -> Vec<Json> {
+        vec![
+            self.block.into(),
+            self.solution.to_string_lossy().as_ref().into(),
+            self.puzzle.to_string_lossy().as_ref().into(),
+        ]
+    }
+
+    fn response(result: &Json) -> Option<SubmitResponse> {
+        Some(SubmitResponse { result: result.clone() })
+    }
+}
+
+// Team ids are numbers, but the chain uses them as JSON object keys too.
+fn team(json: &Json) -> Option<u32> {
+    json.as_u32().or_else(|| json.as_str()?.parse().ok())
+}
+
+fn teams(json: &Json) -> Option<Vec<u32>> {
+    json.as_array()?.iter().map(team).collect()
+}
+
+fn balances(json: &Json) -> Option<BTreeMap<u32, u64>> {
+    json.as_object()?.iter()
+        .map(|(id, balance)| Some((id.parse().ok()?, balance.as_u64()?)))
+        .collect()
+}
+
+/// A JSON-RPC client for `lambdad.py`, one HTTP connection per call.
+pub struct Client {
+    address: String,
+    timeout: Duration,
+    next_id: Cell<u64>,
+}
+
+impl Client {
+    pub fn new(address: &str) -> Client {
+        Client { address: address.to_string(), timeout: Duration::from_secs(60), next_id: Cell::new(1) }
+    }
+
+    pub fn call<M: Method>(&self, method: &M) -> Result<M::Response, RpcError> {
+        let result = self.call_raw(method.name(), method.params())?;
+        M::response(&result).ok_or(RpcError::UnexpectedResult { method: method.name(), result })
+    }
+
+    pub fn call_raw(&self, method: &str, params: Vec<Json>) -> Result<Json, RpcError> {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let request = Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", Json::Number(id as f64)),
+            ("method", method.into()),
+            ("params", Json::Array(params)),
+        ]).to_string();
+
+        let mut stream = TcpStream::connect(&self.address)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        write!(
+            stream,
+            "POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.address, request.len(), request,
+        )?;
+        stream.flush()?;
+
+        let response = Message::read(&mut BufReader::new(stream), true)?;
+        let status = response.start_line.split(' ').nth(1).unwrap_or("");
+        if status != "200" {
+            return Err(RpcError::Http(response.start_line));
+        }
+        let body = String::from_utf8(response.body).map_err(|_| RpcError::Http("the body isn't UTF-8".to_string()))?;
+        let mut json = match Json::parse(&body).map_err(RpcError::Json)? {
+            Json::Object(fields) => fields,
+            json => return Err(RpcError::UnexpectedResult { method: "JSON-RPC", result: json }),
+        };
+        if let Some(error) = json.remove("error").filter(|e| *e != Json::Null) {
+            return Err(RpcError::Server {
+                code: error.get("code").and_then(Json::as_f64).unwrap_or(0.0) as i64,
+                message: error.get("message").and_then(Json::as_str).unwrap_or("unknown error").to_string(),
+            });
+        }
+        json.remove("result").ok_or(RpcError::Http("no result in the response".to_string()))
+    }
+}
+
+/// An HTTP request or response, without the headers that only frame it.
+pub struct Message {
+    pub start_line: String,
+    pub body: Vec<u8>,
+}
+
+impl Message {
+    /// Reads a message; a body without a length runs until the end of the
+    /// stream if `until_eof` and is empty otherwise.
+    pub fn read(reader: &mut impl BufRead, until_eof: bool) -> io::Result<Message> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let mut start_line = String::new();
+        if reader.read_line(&mut start_line)? == 0 {
+            return Err(invalid("connection closed"));
+        }
+        // names are lower-cased
+        let mut headers = vec![];
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(invalid("unterminated headers"));
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').ok_or_else(|| invalid("malformed header"))?;
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+        let header = |name: &str| headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+
+        let mut body = vec![];
+        if header("transfer-encoding").is_some_and(|v| v.eq_ignore_ascii_case("chunked")) {
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line)?;
+                let size = line.split(';').next().unwrap().trim();
+                let size = usize::from_str_radix(size, 16).map_err(|_| invalid("malformed chunk size"))?;
+                if size == 0 {
+                    break;
+                }
+                let start = body.len();
+                body.resize(start + size, 0);
+                reader.read_exact(&mut body[start..])?;
+                reader.read_line(&mut line)?;
+            }
+        } else if let Some(length) = header("content-length") {
+            let length = length.parse().map_err(|_| invalid("malformed content length"))?;
+            body.resize(length, 0);
+            reader.read_exact(&mut body)?;
+        } else if until_eof {
+            reader.read_to_end(&mut body)?;
+        }
+        Ok(Message { start_line: start_line.trim_end().to_string(), body })
+    }
+}
+
+/// Computes the result of a method from its params, or a JSON-RPC error.
+pub type Handler<'a> = dyn FnMut(&str, &[Json]) -> Result<Json, (i64, String)> + 'a;
+
+/// Answers one JSON-RPC request on `stream` the way `lambdad.py` does, with
+/// `handler` computing the results. Stand-in daemons are built on top of this.
+pub fn serve(stream: TcpStream, handler: &mut Handler) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let request = Message::read(&mut reader, false)?;
+    let request = String::from_utf8(request.body).ok().and_then(|body| Json::parse(&body).ok());
+    let id = request.as_ref().and_then(|r| r.get("id")).cloned().unwrap_or(Json::Null);
+    let result = match request.as_ref().and_then(|r| Some((r.get("method")?.as_str()?, r.get("params")))) {
+        None => Err((-32700, "Parse error".to_string())),
+        Some((method, params)) => match params {
+            None => handler(method, &[]),
+            Some(Json::Array(params)) => handler(method, params),
+            Some(_) => Err((-32602, "Invalid params".to_string())),
+        },
+    };
+    let outcome = match result {
+        Ok(result) => ("result", result),
+        Err((code, message)) => ("error", Json::object(vec![
+            ("code", Json::Number(code as f64)),
+            ("message", message.as_str().into()),
+        ])),
+    };
+    let response = Json::object(vec![("jsonrpc", "2.0".into()), ("id", id), outcome]).to_string();
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        response.len(), response,
+    )?;
+    stream.flush()
+}
+
+#[test]
+fn test_client() {
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = std::thread::spawn(move || {
+        let mut calls = vec![];
+        let mut handler = |method: &str, params: &[Json]| {
+            calls.push(format!("{}{}", method, Json::Array(params.to_vec())));
+            let result = match method {
+                "getblockchaininfo" => r#"{"block": 3, "block_subs": 2, "block_ts": 1562328234.5, "total_subs": 10}"#,
+                "getmininginfo" => r#"{"block": 3, "excluded": [1, "42"], "puzzle": "1,1,10", "task": "(0,0)#(0,0)##"}"#,
+                "getbalances" => r#"{"1": 100, "42": 0}"#,
+                "getbalance" if params.is_empty() => "87",
+                "getbalance" if params[0] == Json::from(2) => r#""lots""#,
+                "getbalance" => "100",
+                "getblockinfo" => r#"{"block": 1, "block_ts": 1562328000, "balances": {"1": 100}, "excluded": [],
+                                      "puzzle": "p", "task": "t"}"#,
+                "submit" => r#"{"block": 3, "errors": {}}"#,
+                _ => return Err((-32601, "Method not found".to_string())),
+            };
+            Ok(Json::parse(result).unwrap())
+        };
+        for stream in listener.incoming().take(9) {
+            serve(stream.unwrap(), &mut handler).unwrap();
+        }
+        calls
+    });
+
+    let client = Client::new(&address);
+    assert_eq!(client.call(&GetBlockchainInfo).unwrap(), BlockchainInfo {
+        block: 3, block_subs: 2, block_ts: 1562328234.5, total_subs: 10,
+    });
+    assert_eq!(client.call(&GetMiningInfo).unwrap(), MiningInfo {
+        block: 3, excluded: vec![1, 42], puzzle: "1,1,10".to_string(), task: "(0,0)#(0,0)##".to_string(),
+    });
+    assert_eq!(client.call(&GetBalances).unwrap(), vec![(1, 100), (42, 0)].into_iter().collect());
+    assert_eq!(client.call(&GetBalance { id: None }).unwrap(), 87);
+    assert_eq!(client.call(&GetBalance { id: Some(1) }).unwrap(), 100);
+    let info = client.call(&GetBlockInfo { block: Some(1) }).unwrap();
+    assert_eq!((info.block, info.balances.get(&1), info.task.as_str()), (1, Some(&100), "t"));
+    let submit = Submit { block: 3, solution: PathBuf::from("/b/3/task.sol"), puzzle: PathBuf::from("/b/3/puzzle.desc") };
+    assert_eq!(client.call(&submit).unwrap().result.get("block"), Some(&Json::Number(3.0)));
+    match client.call_raw("getblock", vec![]) {
+        Err(RpcError::Server { code: -32601, .. }) => {}
+        result => panic!("unexpected {:?}", result),
+    }
+    match client.call(&GetBalance { id: Some(2) }) {
+        Err(RpcError::UnexpectedResult { method: "getbalance", .. }) => {}
+        result => panic!("unexpected {:?}", result),
+    }
+
+    assert_eq!(server.join().unwrap(), vec![
+        "getblockchaininfo[]", "getmininginfo[]", "getbalances[]", "getbalance[]", "getbalance[1]",
+        "getblockinfo[1]", r#"submit[3,"/b/3/task.sol","/b/3/puzzle.desc"]"#, "getblock[]", "getbalance[2]",
+    ]);
+}
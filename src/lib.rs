@@ -0,0 +1,21 @@
+#![feature(associated_type_defaults)]
+
+pub mod core;
+pub mod geometry;
+pub mod greedy;
+pub mod grid;
+pub mod inventory;
+pub mod json;
+pub mod lambda;
+pub mod miner;
+pub mod problem;
+pub mod purchase;
+pub mod puzzle;
+pub mod regions;
+pub mod robot;
+pub mod solution;
+pub mod state;
+pub mod terminal;
+#[cfg(test)]
+mod testing;
+pub mod validator;
@@ -1,31 +1,283 @@
-#![feature(associated_type_defaults)]
-#![feature(vec_remove_item)]
-#[macro_use]
-extern crate lazy_static;
-extern crate regex;
-
-mod core;
-mod geometry;
-mod grid;
-mod problem;
-mod robot;
-mod state;
-mod terminal;
-
 use std::env;
 use std::path::Path;
 
-use crate::core::State;
-use crate::problem::Problem;
-use crate::terminal::{Colorizable, TerminalColor};
+use icfpc2019::core::{State as _, Strategy as _};
+use icfpc2019::greedy::Greedy;
+use icfpc2019::json::Json;
+use icfpc2019::lambda::{self, Client, GetBalance, GetBalances, GetBlockInfo, GetBlockchainInfo, RpcError};
+use icfpc2019::miner::{Miner, Outcome, Source};
+use icfpc2019::problem::Problem;
+use icfpc2019::purchase::Purchase;
+use icfpc2019::puzzle::Puzzle;
+use icfpc2019::solution::Solution;
+use icfpc2019::state::{self, BoosterType, State};
+use icfpc2019::terminal::{Colorizable, TerminalColor};
+use icfpc2019::validator;
+
+/// Parses a map, printing a diagnostic instead when it's malformed.
+fn load_map(path: &Path, desc: &str, purchases: &[BoosterType]) -> Option<State> {
+    match Problem::parse_with_purchases(desc, purchases) {
+        Ok(state) => Some(state),
+        Err(error) => {
+            println!("{}", format!("ERROR: {}: {}", path.display(), error).colorize(TerminalColor::Red));
+            println!("{}", error.excerpt(desc));
+            None
+        }
+    }
+}
 
 fn solve(path: &Path) -> std::io::Result<()> {
-    let data = std::fs::read_to_string(path)?;
-    let state = Problem::parse(&data);
+    let state = match load_map(path, &std::fs::read_to_string(path)?, &[]) {
+        Some(state) => state,
+        None => return Ok(()),
+    };
     println!("{}", format!("Solving {} ({})", path.display(), state.info()).colorize(TerminalColor::Magenta));
     Ok(())
 }
 
+fn validate(desc_path: &Path, sol_path: &Path, buy_path: Option<&Path>) -> std::io::Result<bool> {
+    let desc = std::fs::read_to_string(desc_path)?;
+    let sol = std::fs::read_to_string(sol_path)?;
+    let buy = match buy_path {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => String::new(),
+    };
+    let purchases = match Purchase::parse(&buy) {
+        Ok(purchases) => purchases,
+        Err(error) => {
+            println!("{}", format!("ERROR: malformed purchases {}", error).colorize(TerminalColor::Red));
+            return Ok(false);
+        }
+    };
+    let mut state = match load_map(desc_path, &desc, &purchases) {
+        Some(state) => state,
+        None => return Ok(false),
+    };
+    let result = Solution::parse(&sol)
+        .map_err(|e| format!("malformed solution {}", e))
+        .and_then(|mut solution| {
+            Solution::translate(&mut solution, -state.origin());
+            validator::validate(&mut state, &solution).map_err(|e| e.to_string())
+        });
+    match &result {
+        Ok(time) => println!("{}", format!("OK, {}", time).colorize(TerminalColor::Green)),
+        Err(error) => println!("{}", format!("ERROR: {}", error).colorize(TerminalColor::Red)),
+    }
+    Ok(result.is_ok())
+}
+
+/// Number of ticks the greedy solver needs, `None` if it gets stuck or makes an illegal move.
+fn greedy_time(state: State) -> Option<u32> {
+    let mut time = 0;
+    let mut stuck = false;
+    let result = Greedy::run(state, &mut |actions| {
+        time += 1;
+        stuck = actions.iter().all(|a| *a == state::Action::NoOp);
+    });
+    if stuck || result.is_err() { None } else { Some(time) }
+}
+
+fn best_time(meta_path: &Path) -> Option<u32> {
+    let meta = std::fs::read_to_string(meta_path).ok()?;
+    Json::parse(&meta).ok()?.get("bestTime")?.as_u32()
+}
+
+/// Writes the `.buy` file of every map the plan buys a booster for.
+fn plan_purchases(budget: u32, desc_paths: &[String]) -> std::io::Result<()> {
+    let mut options = vec![];
+    for path in desc_paths.iter().map(Path::new) {
+        let desc = std::fs::read_to_string(path)?;
+        let state = match load_map(path, &desc, &[]) {
+            Some(state) => state,
+            None => {
+                options.push(vec![]);
+                continue;
+            }
+        };
+        let max_points = state.max_points();
+        let solver_time = match greedy_time(state) {
+            Some(time) => time,
+            None => {
+                println!("{}", format!("WARNING: {} can't be solved, skipping", path.display()).colorize(TerminalColor::Yellow));
+                options.push(vec![]);
+                continue;
+            }
+        };
+        let baseline = best_time(&path.with_extension("meta")).unwrap_or(solver_time);
+        let mut map_options = vec![];
+        for type_ in [BoosterType::B, BoosterType::F, BoosterType::L, BoosterType::R, BoosterType::C].iter() {
+            if let Some(time) = load_map(path, &desc, &[*type_]).and_then(greedy_time) {
+                let gain = Purchase::estimate_gain(max_points, baseline, solver_time, time);
+                println!("{}: {:?} {} => {} (+{:.0})", path.display(), type_, solver_time, time, gain);
+                if gain > 0.0 {
+                    map_options.push((*type_, gain));
+                }
+            }
+        }
+        options.push(map_options);
+    }
+
+    let plan = Purchase::plan(&options, budget);
+    let mut total_cost = 0;
+    let mut total_gain = 0.0;
+    for (i, type_) in plan.iter().enumerate() {
+        if let Some(type_) = type_ {
+            let gain = options[i].iter().find(|(t, _)| t == type_).unwrap().1;
+            total_cost += Purchase::cost(*type_);
+            total_gain += gain;
+            let buy_path = Path::new(&desc_paths[i]).with_extension("buy");
+            std::fs::write(&buy_path, Purchase::write(&[*type_]))?;
+            println!("{} {}", buy_path.display(), Purchase::write(&[*type_]));
+        }
+    }
+    println!("{}", format!("Total: {} coins, +{:.0} points", total_cost, total_gain).colorize(TerminalColor::Green));
+    Ok(())
+}
+
+/// Writes a map solving the puzzle to `desc_path`, or prints it.
+fn generate(cond_path: &Path, desc_path: Option<&Path>) -> std::io::Result<bool> {
+    let cond = std::fs::read_to_string(cond_path)?;
+    let result = Puzzle::parse(&cond)
+        .map_err(|e| format!("malformed puzzle {}", e))
+        .and_then(|puzzle| puzzle.generate().map_err(|e| e.to_string()));
+    match result {
+        Ok(desc) => {
+            match desc_path {
+                Some(path) => std::fs::write(path, desc)?,
+                None => println!("{}", desc),
+            }
+            Ok(true)
+        }
+        Err(error) => {
+            println!("{}", format!("ERROR: {}: {}", cond_path.display(), error).colorize(TerminalColor::Red));
+            Ok(false)
+        }
+    }
+}
+
+fn check_puzzle(cond_path: &Path, desc_path: &Path) -> std::io::Result<bool> {
+    let cond = std::fs::read_to_string(cond_path)?;
+    let desc = std::fs::read_to_string(desc_path)?;
+    let parsed = Puzzle::parse(&cond)
+        .map_err(|e| format!("{}: malformed puzzle {}", cond_path.display(), e))
+        .and_then(|puzzle| Problem::parse_task(&desc)
+            .map(|task| (puzzle, task))
+            .map_err(|e| format!("{}: {}", desc_path.display(), e)));
+    let (puzzle, task) = match parsed {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            println!("{}", format!("ERROR: {}", error).colorize(TerminalColor::Red));
+            return Ok(false);
+        }
+    };
+    let mut success = true;
+    for (constraint, result) in puzzle.check(&task) {
+        match result {
+            Ok(()) => println!("{}", format!("OK {}", constraint).colorize(TerminalColor::Green)),
+            Err(error) => {
+                println!("{}", format!("FAIL {}: {}", constraint, error).colorize(TerminalColor::Red));
+                success = false;
+            }
+        }
+    }
+    Ok(success)
+}
+
+/// Prints the current block and the balances as the daemon sees them.
+fn chain_info(client: &Client) -> Result<(), RpcError> {
+    let info = client.call(&GetBlockchainInfo)?;
+    let block = client.call(&GetBlockInfo { block: Some(info.block) })?;
+    println!("Block {} opened at {}, {} submissions ({} in total)", info.block, info.block_ts, info.block_subs, info.total_subs);
+    println!("Puzzle: {}", block.puzzle.trim_end());
+    println!("Excluded teams: {:?}", block.excluded);
+    println!("Our balance: {}", client.call(&GetBalance { id: None })?);
+    for (team, balance) in client.call(&GetBalances)? {
+        println!("  team {}: {}", team, balance);
+    }
+    Ok(())
+}
+
+/// Mines block after block until interrupted.
+fn mine(mut miner: Miner) -> std::io::Result<()> {
+    loop {
+        match miner.step() {
+            Ok(Some((block, outcome @ Outcome::Failed(_)))) => {
+                println!("{}", format!("Block {}: {}", block, outcome).colorize(TerminalColor::Red));
+            }
+            Ok(Some((block, outcome))) => {
+                println!("{}", format!("Block {}: {}", block, outcome).colorize(TerminalColor::Green));
+            }
+            Ok(None) => {}
+            Err(error) => println!("{}", format!("WARNING: {}, retrying", error).colorize(TerminalColor::Yellow)),
+        }
+        std::thread::sleep(std::time::Duration::from_secs(5));
+    }
+}
+
 fn main() -> std::io::Result<()> {
-    solve(Path::new(&env::args().into_iter().nth(1).unwrap()))
+    let args = env::args().collect::<Vec<_>>();
+    match args.get(1).map(|s| s.as_str()) {
+        Some("validate") => {
+            let usage = "validate path/to/prob-XXX.desc path/to/prob-XXX.sol [path/to/prob-XXX.buy]";
+            let desc = args.get(2).expect(usage);
+            let sol = Path::new(args.get(3).expect(usage));
+            // the purchases default to the .buy file next to the solution, if any
+            let buy = match args.get(4) {
+                Some(buy) => Some(Path::new(buy).to_path_buf()),
+                None => Some(sol.with_extension("buy")).filter(|p| p.exists()),
+            };
+            if !validate(Path::new(desc), sol, buy.as_deref())? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some("generate") => {
+            let usage = "generate path/to/puzzle.cond [path/to/puzzle.desc]";
+            let cond = args.get(2).expect(usage);
+            if !generate(Path::new(cond), args.get(3).map(Path::new))? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some("check-puzzle") => {
+            let usage = "check-puzzle path/to/puzzle.cond path/to/puzzle.desc";
+            let cond = args.get(2).expect(usage);
+            let desc = args.get(3).expect(usage);
+            if !check_puzzle(Path::new(cond), Path::new(desc))? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some("mine") => {
+            let usage = "mine <blocks|daemon> path/to/blocks [host:port]";
+            let source = match args.get(2).map(|s| s.as_str()) {
+                Some("blocks") => Source::Directory,
+                Some("daemon") => Source::Daemon,
+                _ => panic!("{}", usage),
+            };
+            let dir = args.get(3).expect(usage);
+            let address = args.get(4).map(|s| s.as_str()).unwrap_or(lambda::DEFAULT_ADDRESS);
+            mine(Miner::new(Client::new(address), source, Path::new(dir))?)
+        }
+        Some("chain-info") => {
+            let address = args.get(2).map(|s| s.as_str()).unwrap_or(lambda::DEFAULT_ADDRESS);
+            if let Err(error) = chain_info(&Client::new(address)) {
+                println!("{}", format!("ERROR: {}", error).colorize(TerminalColor::Red));
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some("plan-purchases") => {
+            let usage = "plan-purchases <budget> path/to/prob-XXX.desc...";
+            let budget = args.get(2).expect(usage).parse().expect(usage);
+            plan_purchases(budget, &args[3..])
+        }
+        _ => {
+            args.get(1).expect("path/to/prob-XXX.desc...");
+            for path in args[1..].iter() {
+                solve(Path::new(path))?;
+            }
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,327 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::core::{Problem as _, Strategy as _};
+use crate::greedy::Greedy;
+use crate::lambda::{Client, GetMiningInfo, RpcError, Submit};
+use crate::problem::Problem;
+use crate::puzzle::Puzzle;
+use crate::state::Action;
+
+// the layout of a block directory, as written by lambdad.py
+pub static TASK_FILE: &str = "task.desc";
+pub static PUZZLE_FILE: &str = "puzzle.cond";
+pub static DONE_FILE: &str = ".done";
+static SOLUTION_FILE: &str = "task.sol";
+static PUZZLE_SOLUTION_FILE: &str = "puzzle.desc";
+static JOURNAL_FILE: &str = "mine.journal";
+
+/// Errors worth retrying on the next poll; they aren't journaled.
+#[derive(Debug)]
+pub enum MineError {
+    Io(io::Error),
+    Rpc(RpcError),
+}
+
+impl fmt::Display for MineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MineError::Io(error) => write!(f, "{}", error),
+            MineError::Rpc(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<io::Error> for MineError {
+    fn from(error: io::Error) -> MineError {
+        MineError::Io(error)
+    }
+}
+
+impl From<RpcError> for MineError {
+    fn from(error: RpcError) -> MineError {
+        MineError::Rpc(error)
+    }
+}
+
+/// Where the miner learns about new blocks.
+pub enum Source {
+    /// The block directory `lambdad.py` keeps up to date, e.g. `lambda-client/blocks`.
+    Directory,
+    /// `getmininginfo` calls, the blocks are saved to the directory.
+    Daemon,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    /// Both solutions are written to the block directory, but not submitted yet.
+    Solved,
+    Submitted(String),
+    /// The block can't be mined, e.g. the solver got stuck.
+    Failed(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Solved => write!(f, "solved"),
+            Outcome::Submitted(response) => write!(f, "submitted {}", response),
+            Outcome::Failed(reason) => write!(f, "failed {}", reason),
+        }
+    }
+}
+
+/// The latest outcome of every block, one `<block> <outcome>` line appended
+/// per change so that a restarted miner picks up where it stopped.
+pub struct Journal {
+    path: PathBuf,
+    entries: BTreeMap<u32, Outcome>,
+}
+
+impl Journal {
+    pub fn open(path: &Path) -> io::Result<Journal> {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+        let mut entries = BTreeMap::new();
+        // a line without its newline was cut short by a crash and is ignored
+        for line in data.split_terminator('\n').take(data.matches('\n').count()) {
+            let mut parts = line.splitn(3, ' ');
+            let block = parts.next().and_then(|b| b.parse().ok());
+            let outcome = match (parts.next(), parts.next()) {
+                (Some("solved"), None) => Some(Outcome::Solved),
+                (Some("submitted"), response) => Some(Outcome::Submitted(response.unwrap_or("").to_string())),
+                (Some("failed"), reason) => Some(Outcome::Failed(reason.unwrap_or("").to_string())),
+                _ => None,
+            };
+            match (block, outcome) {
+                (Some(block), Some(outcome)) => entries.insert(block, outcome),
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("malformed journal line '{}'", line))),
+            };
+        }
+        Ok(Journal { path: path.to_path_buf(), entries })
+    }
+
+    pub fn get(&self, block: u32) -> Option<&Outcome> {
+        self.entries.get(&block)
+    }
+
+    pub fn record(&mut self, block: u32, outcome: Outcome) -> io::Result<()> {
+        let line = format!("{} {}", block, outcome).replace(['\r', '\n'], " ");
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(format!("{}\n", line).as_bytes())?;
+        file.sync_data()?;
+        self.entries.insert(block, outcome);
+        Ok(())
+    }
+}
+
+pub struct Block {
+    pub number: u32,
+    pub task: String,
+    pub puzzle: String,
+}
+
+/// Mines the current block: solves its task, generates a map for its puzzle,
+/// checks both locally and submits them through the daemon.
+pub struct Miner {
+    client: Client,
+    source: Source,
+    dir: PathBuf,
+    journal: Journal,
+}
+
+impl Miner {
+    pub fn new(client: Client, source: Source, dir: &Path) -> io::Result<Miner> {
+        fs::create_dir_all(dir)?;
+        let journal = Journal::open(&dir.join(JOURNAL_FILE))?;
+        Ok(Miner { client, source, dir: dir.to_path_buf(), journal })
+    }
+
+    /// Makes progress on the current block, returning what happened to it or
+    /// `None` when there is nothing new to do.
+    pub fn step(&mut self) -> Result<Option<(u32, Outcome)>, MineError> {
+        let block = match self.current_block()? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+        let block_dir = self.dir.join(block.number.to_string());
+        if self.journal.get(block.number).is_none() {
+            let outcome = match Miner::solve(&block) {
+                Ok((solution, desc)) => {
+                    fs::write(block_dir.join(SOLUTION_FILE), solution)?;
+                    fs::write(block_dir.join(PUZZLE_SOLUTION_FILE), desc)?;
+                    Outcome::Solved
+                }
+                Err(reason) => Outcome::Failed(reason),
+            };
+            self.journal.record(block.number, outcome.clone())?;
+            if outcome != Outcome::Solved {
+                return Ok(Some((block.number, outcome)));
+            }
+        }
+        if self.journal.get(block.number) != Some(&Outcome::Solved) {
+            return Ok(None);
+        }
+        // the daemon opens the files itself, relative to its own directory
+        let block_dir = fs::canonicalize(block_dir)?;
+        let response = self.client.call(&Submit {
+            block: block.number,
+            solution: block_dir.join(SOLUTION_FILE),
+            puzzle: block_dir.join(PUZZLE_SOLUTION_FILE),
+        })?;
+        let outcome = Outcome::Submitted(response.result.to_string());
+        self.journal.record(block.number, outcome.clone())?;
+        Ok(Some((block.number, outcome)))
+    }
+
+    fn current_block(&self) -> Result<Option<Block>, MineError> {
+        match self.source {
+            Source::Directory => {
+                let mut latest = None;
+                for entry in fs::read_dir(&self.dir)? {
+                    let path = entry?.path();
+                    let number = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.parse::<u32>().ok());
+                    if let Some(number) = number.filter(|_| path.join(DONE_FILE).exists()) {
+                        latest = latest.max(Some(number));
+                    }
+                }
+                let number = match latest {
+                    Some(number) => number,
+                    None => return Ok(None),
+                };
+                let block_dir = self.dir.join(number.to_string());
+                Ok(Some(Block {
+                    number,
+                    task: fs::read_to_string(block_dir.join(TASK_FILE))?,
+                    puzzle: fs::read_to_string(block_dir.join(PUZZLE_FILE))?,
+                }))
+            }
+            Source::Daemon => {
+                let info = self.client.call(&GetMiningInfo)?;
+                let block_dir = self.dir.join(info.block.to_string());
+                fs::create_dir_all(&block_dir)?;
+                fs::write(block_dir.join(TASK_FILE), &info.task)?;
+                fs::write(block_dir.join(PUZZLE_FILE), &info.puzzle)?;
+                Ok(Some(Block { number: info.block, task: info.task, puzzle: info.puzzle }))
+            }
+        }
+    }
+
+    /// Returns the `.sol` of the task and the `.desc` of the puzzle, both
+    /// checked the way the chain would.
+    pub fn solve(block: &Block) -> Result<(String, String), String> {
+        let state = Problem::load_state(block.task.clone().into_bytes()).map_err(|e| format!("malformed task {}", e))?;
+        let mut solution: Vec<Vec<Action>> = vec![];
+        let mut stuck = false;
+        Greedy::run(state.clone(), &mut |actions| {
+            stuck = actions.iter().all(|a| *a == Action::NoOp);
+            solution.resize(solution.len().max(actions.len()), vec![]);
+            for (id, action) in actions.into_iter().enumerate() {
+                solution[id].push(action);
+            }
+        }).map_err(|(id, e)| format!("the solver made robot {} fail: {}", id, e))?;
+        if stuck {
+            return Err("the solver got stuck on the task".to_string());
+        }
+        Problem::score_solution(state.clone(), &solution).map_err(|e| format!("invalid task solution: {}", e))?;
+        let solution = String::from_utf8(Problem::save_solution(&state, &solution)).unwrap();
+
+        let puzzle = Puzzle::parse(&block.puzzle).map_err(|e| format!("malformed puzzle {}", e))?;
+        let desc = puzzle.generate().map_err(|e| e.to_string())?;
+        Ok((solution, desc))
+    }
+}
+
+#[test]
+fn test_miner() {
+    use std::net::TcpListener;
+    use crate::json::Json;
+    use crate::lambda;
+    use crate::solution::Solution;
+    use crate::testing::TempDir;
+
+    let task = "(0,0),(6,0),(6,6),(0,6)#(0,0)#(2,2),(4,2),(4,4),(2,4)#B(5,5)";
+    let cond = "1,1,10,4,12,1,0,0,0,1,1#(1,1),(8,8)#(5,5)";
+    // the include and exclude lists contradict each other
+    let bad_cond = "2,1,10,4,12,0,0,0,0,0,0#(3,3)#(3,3)";
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let mining_info = |block: u32, puzzle: &str| Ok(Json::object(vec![
+        ("block", block.into()), ("excluded", Json::Array(vec![])), ("puzzle", puzzle.into()), ("task", task.into()),
+    ]));
+    // every call gets the next scripted answer, in order
+    let script = vec![
+        ("getmininginfo", mining_info(1, cond)),
+        ("submit", Err((-32000, "Server error".to_string()))),
+        ("getmininginfo", mining_info(1, cond)),
+        ("submit", Ok(Json::object(vec![("block", 1u32.into())]))),
+        ("getmininginfo", mining_info(1, cond)),
+        ("getmininginfo", mining_info(2, bad_cond)),
+        ("submit", Ok(Json::object(vec![("block", 3u32.into())]))),
+    ];
+    let server = std::thread::spawn(move || {
+        let mut submissions = vec![];
+        for (expected, answer) in script {
+            let (stream, _) = listener.accept().unwrap();
+            lambda::serve(stream, &mut |method, params| {
+                assert_eq!(method, expected);
+                if method == "submit" {
+                    let path = |i: usize| PathBuf::from(params[i].as_str().unwrap());
+                    assert!(path(1).is_absolute() && path(2).is_absolute());
+                    submissions.push((params[0].as_u32().unwrap(), fs::read_to_string(path(1)).unwrap()));
+                }
+                answer.clone()
+            }).unwrap();
+        }
+        submissions
+    });
+
+    let temp = TempDir::new("miner");
+    let dir = temp.path().to_path_buf();
+    let mut miner = Miner::new(Client::new(&address), Source::Daemon, &dir).unwrap();
+    assert!(matches!(miner.step(), Err(MineError::Rpc(RpcError::Server { code: -32000, .. }))));
+    assert_eq!(Journal::open(&dir.join(JOURNAL_FILE)).unwrap().get(1), Some(&Outcome::Solved));
+
+    // a restarted miner doesn't solve the block again, but retries the submission
+    fs::write(dir.join("1").join(PUZZLE_SOLUTION_FILE), "kept").unwrap();
+    let mut miner = Miner::new(Client::new(&address), Source::Daemon, &dir).unwrap();
+    assert_eq!(miner.step().unwrap(), Some((1, Outcome::Submitted(r#"{"block":1}"#.to_string()))));
+    assert_eq!(fs::read_to_string(dir.join("1").join(PUZZLE_SOLUTION_FILE)).unwrap(), "kept");
+    assert_eq!(miner.step().unwrap(), None);
+    match miner.step().unwrap() {
+        Some((2, Outcome::Failed(_))) => {}
+        result => panic!("unexpected {:?}", result),
+    }
+
+    // blocks in the directory only count once lambdad.py is done writing them
+    let block_dir = dir.join("3");
+    fs::create_dir_all(&block_dir).unwrap();
+    fs::write(block_dir.join(TASK_FILE), task).unwrap();
+    fs::write(block_dir.join(PUZZLE_FILE), cond).unwrap();
+    fs::write(block_dir.join(DONE_FILE), "").unwrap();
+    fs::create_dir_all(dir.join("4")).unwrap();
+    let mut miner = Miner::new(Client::new(&address), Source::Directory, &dir).unwrap();
+    assert_eq!(miner.step().unwrap(), Some((3, Outcome::Submitted(r#"{"block":3}"#.to_string()))));
+    assert_eq!(miner.step().unwrap(), None);
+
+    let submissions = server.join().unwrap();
+    assert_eq!(submissions.iter().map(|(block, _)| *block).collect::<Vec<_>>(), vec![1, 1, 3]);
+    let state = Problem::load_state(task.as_bytes().to_vec()).unwrap();
+    assert!(Problem::score_solution(state, &Solution::parse(&submissions[2].1).unwrap()).is_ok());
+
+    let journal = fs::read_to_string(dir.join(JOURNAL_FILE)).unwrap();
+    assert_eq!(journal.lines().map(|l| l.splitn(3, ' ').take(2).collect::<Vec<_>>().join(" ")).collect::<Vec<_>>(), vec![
+        "1 solved", "1 submitted", "2 failed", "3 solved", "3 submitted",
+    ]);
+    fs::write(dir.join(JOURNAL_FILE), format!("{}4 sol", journal)).unwrap();
+    assert_eq!(Journal::open(&dir.join(JOURNAL_FILE)).unwrap().get(4), None);
+    fs::write(dir.join(JOURNAL_FILE), "4 mined\n").unwrap();
+    assert!(Journal::open(&dir.join(JOURNAL_FILE)).is_err());
+}
@@ -1,99 +1,617 @@
 use std::cmp;
-use std::collections::HashMap;
-
-use regex::Regex;
+use std::fmt;
 
+use crate::core;
 use crate::geometry::Point2D;
-use crate::grid::{Grid, GridCell};
-use crate::state::{Booster, BoosterType, State};
+use crate::grid::{Grid, GridCell, NEIGHBOURS_4};
+use crate::inventory::Inventory;
+use crate::solution::Solution;
+use crate::state::{Action, Booster, BoosterType, State};
+use crate::validator::{self, ValidationError};
 
 pub struct Problem;
 
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Section {
+    Map,
+    Initial,
+    Obstacles,
+    Boosters,
+}
+
+/// Largest width or height of a map.
+const MAX_SIZE: i32 = 10_000;
+
+const NO_OWNER: u32 = u32::MAX;
+
+static SECTIONS: [Section; 4] = [Section::Map, Section::Initial, Section::Obstacles, Section::Boosters];
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Section::Map => write!(f, "map"),
+            Section::Initial => write!(f, "initial position"),
+            Section::Obstacles => write!(f, "obstacles"),
+            Section::Boosters => write!(f, "boosters"),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub section: Section,
+    pub offset: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new<S: Into<String>>(section: Section, offset: usize, message: S) -> ParseError {
+        ParseError { section, offset, message: message.into() }
+    }
+
+    /// Up to 30 characters on both sides of the error, within its line, with
+    /// a caret under it.
+    pub fn excerpt(&self, source: &str) -> String {
+        let bytes = source.as_bytes();
+        let offset = cmp::min(self.offset, bytes.len());
+        let line_start = bytes[..offset].iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+        let line_end = bytes[offset..].iter().position(|b| *b == b'\n' || *b == b'\r').map_or(bytes.len(), |i| offset + i);
+        let start = cmp::max(line_start, offset.saturating_sub(30));
+        let end = cmp::min(line_end, offset + 30);
+        let column = String::from_utf8_lossy(&bytes[start..offset]).chars().count();
+        format!("{}\n{}^", String::from_utf8_lossy(&bytes[start..end]), " ".repeat(column))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} section at byte {}: {}", self.section, self.offset, self.message)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum ExportError {
+    NoPassableCells,
+    Disconnected { cell: Point2D },
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportError::NoPassableCells => write!(f, "the map has no passable cells"),
+            ExportError::Disconnected { cell } => write!(f, "({},{}) can't be reached from the rest of the map", cell.x, cell.y),
+        }
+    }
+}
+
+impl core::Problem for Problem {
+    type State = State;
+    type Error = ValidationError;
+    type ParseError = ParseError;
+
+    fn load_state(data: Vec<u8>) -> Result<State, ParseError> {
+        Problem::parse(&String::from_utf8_lossy(&data))
+    }
+
+    /// Writes the solution in map coordinates, moving teleport targets by the origin of `state`.
+    fn save_solution(state: &State, solution: &[Vec<Action>]) -> Vec<u8> {
+        let mut solution = solution.to_vec();
+        Solution::translate(&mut solution, state.origin());
+        Solution::write(&solution).into_bytes()
+    }
+
+    fn score_solution(mut state: State, solution: &[Vec<Action>]) -> Result<u32, ValidationError> {
+        validator::validate(&mut state, solution)
+    }
+}
+
 impl Problem {
-    pub fn parse(s: &str) -> State {
-        let raw_parts = s.split('#').collect::<Vec<&str>>();
-        assert_eq!(raw_parts.len(), 4);
-        let raw_map = raw_parts[0];
-        let raw_initial = raw_parts[1];
-        let raw_obstacles = raw_parts[2];
-        let raw_boosters = raw_parts[3];
-        
-        let obstacles = raw_obstacles.split(';')
-            .filter(|s| s.len() > 0)
-            .map(|s| Poly::new(Self::parse_points(s)))
-            .collect::<Vec<_>>();
-        let boosters = raw_boosters.split(';')
-            .filter(|s| s.len() > 0)
-            .map(|s| Self::parse_booster(s))
-            .collect::<Vec<_>>();
-        let map = Poly::new(Self::parse_points(raw_map));
-        let initial_position = Self::parse_point(raw_initial);
+    pub fn parse(s: &str) -> Result<State, ParseError> {
+        Self::parse_with_purchases(s, &[])
+    }
 
+    /// Parses a map that starts with the boosters bought for it already in the inventory.
+    pub fn parse_with_purchases(s: &str, purchases: &[BoosterType]) -> Result<State, ParseError> {
+        let Task { mut map, initial_position, mut obstacles, mut boosters, offsets } = Self::parse_task(s)?;
+
+        // the grid starts at the bottom left corner of the map, solutions are written in map coordinates
         let (bottom_left, top_right) = map.bbox();
-        assert_eq!(bottom_left.x, 0);
-        assert_eq!(bottom_left.y, 0);
+        let size = |min: i32, max: i32| min.checked_neg().and(max.checked_sub(min)).filter(|size| *size <= MAX_SIZE);
+        let (width, height) = match (size(bottom_left.x, top_right.x), size(bottom_left.y, top_right.y)) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return Err(ParseError::new(Section::Map, offsets.map, "the map is too large")),
+        };
+
+        let errors = Poly::validate_map(&map, &obstacles).into_iter()
+            .filter(|(_, error)| !matches!(error, PolyError::TouchingMap { .. }))
+            .collect::<Vec<_>>();
+        if let Some((contour, _)) = errors.first() {
+            // points at the first problem and lists all of them
+            let (section, offset) = match contour {
+                Contour::Map => (Section::Map, offsets.map),
+                Contour::Obstacle(i) => (Section::Obstacles, offsets.obstacles[*i]),
+            };
+            let message = errors.iter().map(|(contour, error)| format!("{}: {}", contour, error)).collect::<Vec<_>>();
+            return Err(ParseError::new(section, offset, message.join("; ")));
+        }
 
-        let width = top_right.x;
-        let height = top_right.y;
+        map.translate(-bottom_left);
+        obstacles.iter_mut().for_each(|o| o.translate(-bottom_left));
 
-        let mut grid = Grid::new(width as u16, height as u16, GridCell::Void);
+        let mut grid = Grid::new(width as u32, height as u32, GridCell::Void);
         map.project(&mut grid, GridCell::Free);
         obstacles.iter().for_each(|o| {
             o.project(&mut grid, GridCell::Obstacle);
         });
 
-        State::new(grid, boosters, initial_position)
+        let in_bbox = |p: Point2D| p.x >= bottom_left.x && p.x < top_right.x && p.y >= bottom_left.y && p.y < top_right.y;
+        let on_free_cell = |p: Point2D| in_bbox(p) && !grid[p - bottom_left].is_obstacle();
+        if !on_free_cell(initial_position) {
+            return Err(ParseError::new(Section::Initial, offsets.initial, "the robot doesn't start on a free cell"));
+        }
+        for (booster, offset) in boosters.iter_mut().zip(offsets.boosters.iter()) {
+            if !on_free_cell(booster.position) {
+                return Err(ParseError::new(Section::Boosters, *offset, "the booster isn't on a free cell"));
+            }
+            booster.position = booster.position - bottom_left;
+        }
+        let initial_position = initial_position - bottom_left;
+
+        let mut state = State::new(grid, boosters, initial_position, Inventory::purchased(purchases));
+        state.set_origin(bottom_left);
+        Ok(state)
+    }
+
+    /// Reads the contours and positions of a `.desc` file without checking them.
+    pub fn parse_task(s: &str) -> Result<Task, ParseError> {
+        let mut tokens = Tokenizer::new(s);
+        let mut offsets = TaskOffsets { map: tokens.offset(), ..TaskOffsets::default() };
+        let map = Poly::new(tokens.points()?);
+        tokens.next_section()?;
+        offsets.initial = tokens.offset();
+        let initial_position = tokens.point()?;
+
+        tokens.next_section()?;
+        let mut obstacles = vec![];
+        if !tokens.at_section_end() {
+            loop {
+                offsets.obstacles.push(tokens.offset());
+                obstacles.push(Poly::new(tokens.points()?));
+                if !tokens.eat(b';') {
+                    break;
+                }
+            }
+        }
+
+        tokens.next_section()?;
+        let mut boosters = vec![];
+        if !tokens.at_section_end() {
+            loop {
+                offsets.boosters.push(tokens.offset());
+                boosters.push(tokens.booster()?);
+                if !tokens.eat(b';') {
+                    break;
+                }
+            }
+        }
+        tokens.end()?;
+        Ok(Task { map, initial_position, obstacles, boosters, offsets })
     }
 
-    fn parse_booster(s: &str) -> Booster {
-        let type_ = match s.chars().nth(0).unwrap() {
-            'B' => BoosterType::B,
-            'C' => BoosterType::C,
-            'F' => BoosterType::F,
-            'L' => BoosterType::L,
-            'R' => BoosterType::R,
-            'X' => BoosterType::X,
-            _ => panic!("Invalid booster type"),
+    /// Writes the map of a state as a `.desc` file in the coordinates of the
+    /// original map, with robot 0 as the initial position.
+    ///
+    /// The outline of the passable cells becomes the map contour and every
+    /// enclosed group of obstacles an obstacle, so the passable cells have to
+    /// be connected.
+    pub fn write(state: &State) -> Result<String, ExportError> {
+        let grid = state.grid();
+        let passable = |p: Point2D| grid.contains(p) && !grid[p].is_obstacle();
+        let start = grid.cells().map(|(p, _)| p).find(|p| passable(*p)).ok_or(ExportError::NoPassableCells)?;
+
+        let index = |p: Point2D| grid.linear_index(p);
+        let flood = |labels: &mut Vec<Option<usize>>, starts: Vec<Point2D>, label: usize, inside: &dyn Fn(Point2D) -> bool| {
+            let mut stack = starts;
+            while let Some(p) = stack.pop() {
+                if labels[index(p)].is_none() {
+                    labels[index(p)] = Some(label);
+                    stack.extend(NEIGHBOURS_4.iter().map(|d| p + *d).filter(|q| grid.contains(*q) && inside(*q)));
+                }
+            }
         };
-        Booster {
-            type_,
-            position: Self::parse_point(&s[1..]),
+
+        let mut labels = vec![None; grid.area()];
+        flood(&mut labels, vec![start], 0, &passable);
+        if let Some((cell, _)) = grid.cells().find(|(p, _)| passable(*p) && labels[index(*p)].is_none()) {
+            return Err(ExportError::Disconnected { cell });
         }
+        // label 1 is outside the map, every hole gets its own label from 2 on
+        let border = grid.cells()
+            .map(|(p, _)| p)
+            .filter(|p| !passable(*p) && (p.x == 0 || p.y == 0 || p.x + 1 == grid.width as i32 || p.y + 1 == grid.height as i32))
+            .collect::<Vec<_>>();
+        flood(&mut labels, border, 1, &|p| !passable(p));
+        let mut holes = vec![];
+        for (p, _) in grid.cells() {
+            if labels[index(p)].is_none() {
+                holes.push(p);
+                flood(&mut labels, vec![p], holes.len() + 1, &|p| !passable(p));
+            }
+        }
+
+        let origin = state.origin();
+        let write_points = |points: &[Point2D]| {
+            points.iter().map(|p| format!("({},{})", p.x + origin.x, p.y + origin.y)).collect::<Vec<_>>().join(",")
+        };
+        let map = Poly::trace(start, |p| grid.contains(p) && labels[index(p)] != Some(1));
+        let obstacles = holes.iter().enumerate()
+            .map(|(i, hole)| write_points(&Poly::trace(*hole, |p| grid.contains(p) && labels[index(p)] == Some(i + 2)).contour))
+            .collect::<Vec<_>>();
+        let mut boosters = vec![];
+        for type_ in [BoosterType::B, BoosterType::F, BoosterType::L, BoosterType::R, BoosterType::C, BoosterType::X].iter() {
+            let mut positions = state.booster_positions(*type_);
+            positions.sort_by_key(|p| (p.y, p.x));
+            boosters.extend(positions.into_iter().map(|p| format!("{:?}{}", type_, write_points(&[p]))));
+        }
+        Ok(format!(
+            "{}#{}#{}#{}",
+            write_points(&map.contour),
+            write_points(&[state.robot(0).position]),
+            obstacles.join(";"),
+            boosters.join(";"),
+        ))
     }
+}
+
+/// The contents of a `.desc` file as written.
+pub struct Task {
+    pub map: Poly,
+    pub initial_position: Point2D,
+    pub obstacles: Vec<Poly>,
+    pub boosters: Vec<Booster>,
+    offsets: TaskOffsets,
+}
+
+/// Where the parts of a task start in its file.
+#[derive(Default)]
+struct TaskOffsets {
+    map: usize,
+    initial: usize,
+    obstacles: Vec<usize>,
+    boosters: Vec<usize>,
+}
+
+/// Reads the tokens of a `.desc` file in place, whitespace between them is skipped.
+struct Tokenizer<'a> {
+    data: &'a [u8],
+    offset: usize,
+    section: usize,
+}
 
-    fn parse_points(s: &str) -> Vec<Point2D> {
-        lazy_static! {
-            static ref POINTS_REGEX: Regex = Regex::new(r"\((\d+),(\d+)\)(?:,|$)").unwrap();
+impl<'a> Tokenizer<'a> {
+    fn new(s: &'a str) -> Tokenizer<'a> {
+        Tokenizer { data: s.as_bytes(), offset: 0, section: 0 }
+    }
+
+    /// Offset of the next token.
+    fn offset(&mut self) -> usize {
+        while self.data.get(self.offset).is_some_and(|c| c.is_ascii_whitespace()) {
+            self.offset += 1;
+        }
+        self.offset
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        let offset = self.offset();
+        self.data.get(offset).cloned()
+    }
+
+    /// Consumes `c` if it is the next token.
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.offset += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), ParseError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}'", c as char)))
+        }
+    }
+
+    fn at_section_end(&mut self) -> bool {
+        matches!(self.peek(), None | Some(b'#'))
+    }
+
+    fn next_section(&mut self) -> Result<(), ParseError> {
+        let next = SECTIONS[self.section + 1];
+        match self.peek() {
+            Some(b'#') => {
+                self.offset += 1;
+                self.section += 1;
+                Ok(())
+            }
+            None => Err(ParseError::new(next, self.offset, format!("missing {} section", next))),
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+
+    fn end(&mut self) -> Result<(), ParseError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(b'#') => Err(self.error("unexpected '#' after the boosters")),
+            Some(_) => Err(self.unexpected()),
         }
-        POINTS_REGEX.captures_iter(s).map(|c|
-            Point2D::new(
-                c[1].parse::<i32>().unwrap(),
-                c[2].parse::<i32>().unwrap()
-            )
-        ).collect()
     }
 
-    fn parse_point(s: &str) -> Point2D {
-        lazy_static! {
-            static ref POINT_REGEX: Regex = Regex::new(r"^\((\d+),(\d+)\)$").unwrap();
+    fn number(&mut self) -> Result<i32, ParseError> {
+        let start = self.offset();
+        let negative = self.data.get(self.offset) == Some(&b'-');
+        if negative {
+            self.offset += 1;
+        }
+        let mut value: i32 = 0;
+        let mut digits = 0;
+        while let Some(c) = self.data.get(self.offset).filter(|c| c.is_ascii_digit()) {
+            let digit = (c - b'0') as i32;
+            value = value.checked_mul(10)
+                .and_then(|v| if negative { v.checked_sub(digit) } else { v.checked_add(digit) })
+                .ok_or_else(|| ParseError::new(SECTIONS[self.section], start, "coordinate out of range"))?;
+            self.offset += 1;
+            digits += 1;
+        }
+        if digits == 0 {
+            self.offset = start;
+            return Err(self.error("expected a number"));
         }
-        let captures = POINT_REGEX.captures(s).unwrap();
-        Point2D::new(
-            captures[1].parse::<i32>().unwrap(),
-            captures[2].parse::<i32>().unwrap()
-        )
+        Ok(value)
+    }
+
+    fn point(&mut self) -> Result<Point2D, ParseError> {
+        self.expect(b'(')?;
+        let x = self.number()?;
+        self.expect(b',')?;
+        let y = self.number()?;
+        self.expect(b')')?;
+        Ok(Point2D::new(x, y))
+    }
+
+    /// A non-empty `,` separated list of points.
+    fn points(&mut self) -> Result<Vec<Point2D>, ParseError> {
+        let mut points = vec![self.point()?];
+        while self.eat(b',') {
+            points.push(self.point()?);
+        }
+        Ok(points)
+    }
+
+    fn booster(&mut self) -> Result<Booster, ParseError> {
+        let type_ = match self.peek() {
+            Some(b'B') => BoosterType::B,
+            Some(b'C') => BoosterType::C,
+            Some(b'F') => BoosterType::F,
+            Some(b'L') => BoosterType::L,
+            Some(b'R') => BoosterType::R,
+            Some(b'X') => BoosterType::X,
+            Some(c) => return Err(self.error(format!("unknown booster type '{}'", c as char))),
+            None => return Err(self.error("expected a booster")),
+        };
+        self.offset += 1;
+        Ok(Booster { type_, position: self.point()? })
+    }
+
+    fn unexpected(&mut self) -> ParseError {
+        match self.peek() {
+            Some(c) if c.is_ascii_graphic() => self.error(format!("unexpected '{}'", c as char)),
+            Some(c) => self.error(format!("unexpected byte 0x{:02x}", c)),
+            None => self.error("unexpected end of file"),
+        }
+    }
+
+    fn error<S: Into<String>>(&mut self, message: S) -> ParseError {
+        let offset = self.offset();
+        ParseError::new(SECTIONS[self.section], offset, message)
     }
 }
 
 #[test]
 fn test_parsing() {
-    assert_eq!(Problem::parse_point("(0,1)"), Point2D::new(0, 1));
-    assert_eq!(Problem::parse_points("(0,1),(1,0)"), vec!(Point2D::new(0, 1), Point2D::new(1, 0)));
-    assert_eq!(Problem::parse_booster("B(1,0)"), Booster { type_: BoosterType::B, position: Point2D::new(1, 0) });
+    assert_eq!(Tokenizer::new("(0,1)").point(), Ok(Point2D::new(0, 1)));
+    assert_eq!(Tokenizer::new(" ( -3 , 12 ) ").point(), Ok(Point2D::new(-3, 12)));
+    assert_eq!(Tokenizer::new("(0,1),(1,0)").points(), Ok(vec!(Point2D::new(0, 1), Point2D::new(1, 0))));
+    assert_eq!(Tokenizer::new("B(1,0)").booster(), Ok(Booster { type_: BoosterType::B, position: Point2D::new(1, 0) }));
+    assert_eq!(Tokenizer::new("(-2147483648,2147483647)").point(), Ok(Point2D::new(i32::MIN, i32::MAX)));
+    assert_eq!(Tokenizer::new("(2147483648,0)").point().unwrap_err().message, "coordinate out of range");
+    assert_eq!(Tokenizer::new("(1,-)").point().unwrap_err().offset, 3);
+    assert_eq!(Tokenizer::new("(1 1)").point().unwrap_err().message, "expected ','");
+
+    let error = |s: &str| Problem::parse(s).err().map(|e| (e.section, e.offset));
+    let map = "(0,0),(3,0),(3,3),(0,3)";
+    assert!(Problem::parse(&format!("{}#(1,1)##B(0,0)\n", map)).is_ok());
+    assert!(Problem::parse(" (0,0), (3,0),\n(3,3),(0,3) # (0,2) # (1,1),(2,1),(2,2),(1,2) # B(0,0) ; L(2,2)\n").is_ok());
+    assert_eq!(error(&format!("{}#(1,1)#(0,0),(1,0),(1,1),(0,1);#", map)), Some((Section::Obstacles, 54)));
+    assert_eq!(error(&format!("{}#(1,1)#", map)), Some((Section::Boosters, 30)));
+    assert_eq!(error(&format!("{}#(1,1)###", map)), Some((Section::Boosters, 31)));
+    assert_eq!(error("(0,0),(3,0),(3,3)x(0,3)#(1,1)##"), Some((Section::Map, 17)));
+    assert_eq!(error("(0,0),(3,0),(3,3),#(1,1)##"), Some((Section::Map, 18)));
+    assert_eq!(error("(0,0),(3,0),(3,3)#(1,1)##"), Some((Section::Map, 0)));
+    assert_eq!(error("(1,1),(3,1),(3,3),(1,3)#(0,0)##"), Some((Section::Initial, 24)));
+    assert_eq!(error("(1,1),(3,1),(3,3),(1,3)#(1,1)##B(3,2)"), Some((Section::Boosters, 31)));
+    assert_eq!(error("(0,0),(20000,0),(20000,1),(0,1)#(0,0)##"), Some((Section::Map, 0)));
+    assert_eq!(error("(-2147483648,0),(0,0),(0,1),(-2147483648,1)#(-1,0)##"), Some((Section::Map, 0)));
+    assert_eq!(error(&format!("{}#(1;1)##", map)), Some((Section::Initial, 26)));
+    assert_eq!(error(&format!("{}#(5,1)##", map)), Some((Section::Initial, 24)));
+    assert_eq!(error(&format!("{}#(1,1)#(0,0),(1,0),(1,1),(0,1);(2,2)(2,3)#", map)), Some((Section::Obstacles, 59)));
+    assert_eq!(error(&format!("{}#(1,1)##B(0,0);Q(1,1)", map)), Some((Section::Boosters, 38)));
+    assert_eq!(error(&format!("{}#(1,1)##B(0,0);C(3,1)", map)), Some((Section::Boosters, 38)));
+    assert_eq!(error(&format!("{}#(1,99999999999)##", map)), Some((Section::Initial, 27)));
+
+    assert_eq!(error("(0,0),(2,0),(2,1),(1,1),(1,-1),(0,-1)#(0,0)##"), Some((Section::Map, 0)));
+    let e = Problem::parse(&format!("{}#(0,0)#(1,1),(2,1),(2,2),(1,2);(1,1),(2,1),(2,2),(1,2)#", map)).err().unwrap();
+    assert_eq!((e.section, e.offset), (Section::Obstacles, 54));
+    assert_eq!(e.message, "obstacle 1: overlaps obstacle 0");
+    // every problem is listed
+    let e = Problem::parse(&format!("{}#(0,0)#(1,1),(2,1),(2,2),(1,2);(1,1),(2,1),(2,2),(1,2);(2,2),(4,2),(4,3),(2,3)#", map)).err().unwrap();
+    assert_eq!(e.message, [
+        "obstacle 2: the edge between vertices 0 and 1 goes outside of the map",
+        "obstacle 2: the edge between vertices 1 and 2 goes outside of the map",
+        "obstacle 2: the edge between vertices 2 and 3 goes outside of the map",
+        "obstacle 1: overlaps obstacle 0",
+    ].join("; "));
+
+    let state = Problem::parse("(-2,1),(1,1),(1,4),(-2,4)#(-1,2)#(0,2),(1,2),(1,3),(0,3)#B(-2,1)").unwrap();
+    assert_eq!(state.origin(), Point2D::new(-2, 1));
+    assert_eq!((state.grid().width, state.grid().height), (3, 3));
+    assert_eq!(state.robot(0).position, Point2D::new(1, 1));
+    assert_eq!(state.grid()[Point2D::new(2, 1)], GridCell::Obstacle);
+    assert_eq!(state.booster_positions(BoosterType::B), vec![Point2D::new(0, 0)]);
+
+    let desc = format!("{}#(1,1)##B(0,0);Q(1,1)", map);
+    let e = Problem::parse(&desc).err().unwrap();
+    assert_eq!(e.to_string(), "boosters section at byte 38: unknown booster type 'Q'");
+    assert_eq!(e.excerpt(&desc), ",0),(3,3),(0,3)#(1,1)##B(0,0);Q(1,1)\n".to_string() + &" ".repeat(30) + "^");
+    let desc = format!("{}\n#(1,1)\r\n#\n#B(0,0);Q(1,1)\n", map);
+    let e = Problem::parse(&desc).err().unwrap();
+    assert_eq!(e.excerpt(&desc), "#B(0,0);Q(1,1)\n        ^");
+    let e = Problem::parse(&format!("{}#(1,x)##", map)).err().unwrap();
+    assert_eq!(e.excerpt(&format!("{}#(1,x)##", map)), "(0,0),(3,0),(3,3),(0,3)#(1,x)##\n".to_string() + &" ".repeat(27) + "^");
+}
+
+#[test]
+fn test_problem() {
+    use crate::core::Problem as _;
+    use crate::state::Action::*;
+    let solution = vec![vec![
+        MoveUp, MoveUp, MoveRight, MoveRight, MoveDown, MoveDown, MoveRight,
+        MoveRight, MoveUp, MoveUp, MoveRight, MoveDown, MoveRight,
+    ]];
+    let data = include_bytes!("../part-1-initial/prob-001.desc").to_vec();
+    let load = |data: Vec<u8>| Problem::load_state(data).unwrap();
+    assert_eq!(Problem::save_solution(&load(data.clone()), &solution), include_bytes!("../part-1-initial/prob-001.sol").to_vec());
+    assert_eq!(Problem::score_solution(load(data.clone()), &solution), Ok(13));
+    let error = Problem::score_solution(load(data.clone()), &[solution[0][..12].to_vec()]).unwrap_err();
+    assert_eq!((error.time, error.action), (12, None));
+    let error = Problem::score_solution(load(data), &[vec![MoveLeft]]).unwrap_err();
+    assert_eq!((error.robot, error.time, error.action), (0, 0, Some(MoveLeft)));
+
+    // teleport targets are saved in map coordinates and come back in grid ones
+    let data = b"(2,1),(5,1),(5,3),(2,3)#(2,1)##R(3,1)".to_vec();
+    let solution = vec![vec![MoveRight, InstallBeacon, MoveRight, MoveUp, Teleport(Point2D::new(1, 0)), MoveUp, Attach(Point2D::new(-1, 0))]];
+    let saved = Problem::save_solution(&load(data.clone()), &solution);
+    assert_eq!(saved, b"DRDWT(3,1)WB(-1,0)".to_vec());
+    let mut parsed = Solution::parse(&String::from_utf8(saved).unwrap()).unwrap();
+    Solution::translate(&mut parsed, -load(data.clone()).origin());
+    assert_eq!(parsed, solution);
 }
 
-struct Poly {
-    contour: Vec<Point2D>
+#[test]
+fn test_export() {
+    let desc = "(0,0),(3,0),(3,3),(0,3)#(0,0)#(1,1),(2,1),(2,2),(1,2)#B(2,0);X(0,2)";
+    assert_eq!(Problem::write(&Problem::parse(desc).unwrap()), Ok(desc.to_string()));
+    // obstacles on the border become part of the outline, the origin is kept
+    let state = Problem::parse("(-1,-1),(4,-1),(4,3),(-1,3)#(0,0)#(-1,-1),(4,-1),(4,0),(-1,0);(3,1),(4,1),(4,2),(3,2)#").unwrap();
+    assert_eq!(Problem::write(&state), Ok("(-1,0),(4,0),(4,1),(3,1),(3,2),(4,2),(4,3),(-1,3)#(0,0)##".to_string()));
+    let state = Problem::parse("(0,0),(3,0),(3,3),(0,3)#(0,0)#(1,0),(2,0),(2,3),(1,3)#").unwrap();
+    assert_eq!(Problem::write(&state), Err(ExportError::Disconnected { cell: Point2D::new(2, 0) }));
+
+    let boosters = |state: &State| {
+        let mut boosters = [BoosterType::B, BoosterType::C, BoosterType::X].iter()
+            .flat_map(|type_| state.booster_positions(*type_).into_iter().map(move |p| (p.x, p.y, *type_ as u8)))
+            .collect::<Vec<_>>();
+        boosters.sort();
+        boosters
+    };
+    for path in ["part-1-initial/prob-002.desc", "part-2-teleports/prob-151.desc", "part-3-clones/prob-230.desc"].iter() {
+        let state = Problem::parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let copy = Problem::parse(&Problem::write(&state).unwrap()).unwrap();
+        assert_eq!(copy.grid().cells().collect::<Vec<_>>(), state.grid().cells().collect::<Vec<_>>(), "{}", path);
+        assert_eq!(copy.origin(), state.origin());
+        assert_eq!(boosters(&copy), boosters(&state));
+    }
+}
+
+#[test]
+fn test_all_maps() {
+    let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut count = 0;
+    for dir in &["part-1-initial", "part-2-teleports", "part-3-clones"] {
+        for entry in std::fs::read_dir(root.join(dir)).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "desc") {
+                continue;
+            }
+            let state = Problem::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            let grid = state.grid();
+            assert_eq!(grid.area(), (grid.width * grid.height) as usize, "{}", path.display());
+            assert_eq!(
+                grid.num_obstacles + grid.num_void + grid.num_wrapped + grid.num_free,
+                grid.width * grid.height,
+                "{}", path.display()
+            );
+            assert!(grid.num_wrapped > 0, "{}", path.display());
+            count += 1;
+        }
+    }
+    assert_eq!(count, 300);
+
+    let state = Problem::parse(include_str!("../part-2-teleports/prob-211.desc")).unwrap();
+    assert_eq!((state.grid().width, state.grid().height), (399, 400));
+    assert_eq!(state.max_points(), 17285);
+}
+
+/// A contour of a map file, `Map` or the index of an obstacle.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Contour {
+    Map,
+    Obstacle(usize),
+}
+
+impl fmt::Display for Contour {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Contour::Map => write!(f, "map"),
+            Contour::Obstacle(i) => write!(f, "obstacle {}", i),
+        }
+    }
+}
+
+/// A problem with a contour, edges are given by the indices of their vertices.
+#[derive(PartialEq, Eq, Debug)]
+pub enum PolyError {
+    TooFewVertices,
+    NotAxisAligned { edge: (usize, usize) },
+    SelfIntersecting { edges: ((usize, usize), (usize, usize)) },
+    Clockwise,
+    TooLarge,
+    OutsideMap { edge: (usize, usize) },
+    TouchingMap { edge: (usize, usize) },
+    Overlapping { obstacle: usize },
+}
+
+impl fmt::Display for PolyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolyError::TooFewVertices => write!(f, "less than 4 vertices"),
+            PolyError::NotAxisAligned { edge: (a, b) } => write!(f, "the edge between vertices {} and {} is not axis-aligned", a, b),
+            PolyError::SelfIntersecting { edges: ((a, b), (c, d)) } => {
+                write!(f, "the edges between vertices {} and {} and between vertices {} and {} intersect", a, b, c, d)
+            }
+            PolyError::Clockwise => write!(f, "the vertices are in clockwise order"),
+            PolyError::TooLarge => write!(f, "larger than {}x{}", MAX_SIZE, MAX_SIZE),
+            PolyError::OutsideMap { edge: (a, b) } => write!(f, "the edge between vertices {} and {} goes outside of the map", a, b),
+            PolyError::TouchingMap { edge: (a, b) } => write!(f, "the edge between vertices {} and {} touches the border of the map", a, b),
+            PolyError::Overlapping { obstacle } => write!(f, "overlaps obstacle {}", obstacle),
+        }
+    }
+}
+
+pub struct Poly {
+    pub contour: Vec<Point2D>
 }
 
 impl Poly {
@@ -104,10 +622,10 @@ impl Poly {
     }
 
     pub fn bbox(&self) -> (Point2D, Point2D) {
-        let mut min_x = i32::max_value();
-        let mut max_x = 0;
-        let mut min_y = i32::max_value();
-        let mut max_y = 0;
+        let mut min_x = i32::MAX;
+        let mut max_x = i32::MIN;
+        let mut min_y = i32::MAX;
+        let mut max_y = i32::MIN;
         for p in self.contour.iter() {
             min_x = cmp::min(min_x, p.x);
             max_x = cmp::max(max_x, p.x);
@@ -117,42 +635,284 @@ impl Poly {
         (Point2D::new(min_x, min_y), Point2D::new(max_x, max_y))
     }
 
-    pub fn project(&self, grid: &mut Grid, cell: GridCell) {
-        let mut verticals: HashMap<i32, Vec<Vertical>> = HashMap::new();
+    pub fn translate(&mut self, offset: Point2D) {
+        self.contour.iter_mut().for_each(|p| *p = *p + offset);
+    }
+
+    fn edge(&self, i: usize) -> (Point2D, Point2D) {
+        let (a, b) = self.edge_vertices(i);
+        (self.contour[a], self.contour[b])
+    }
+
+    fn edge_vertices(&self, i: usize) -> (usize, usize) {
+        (i, (i + 1) % self.contour.len())
+    }
+
+    /// Twice the signed area, positive for counter-clockwise contours.
+    fn signed_area(&self) -> i128 {
+        (0..self.contour.len())
+            .map(|i| {
+                let (a, b) = self.edge(i);
+                a.x as i128 * b.y as i128 - b.x as i128 * a.y as i128
+            })
+            .sum()
+    }
+
+    /// Checks that the contour is a simple counter-clockwise polygon with
+    /// axis-aligned edges, as `project` expects.
+    pub fn validate(&self) -> Vec<PolyError> {
+        if self.contour.len() < 4 {
+            return vec![PolyError::TooFewVertices];
+        }
+        let mut errors = vec![];
+        let mut vertical = vec![];
+        let mut horizontal = vec![];
         for i in 0..self.contour.len() {
-            let a = self.contour[i];
-            let b = self.contour[(i + 1) % self.contour.len()];
-            if a.x == b.x {
-                if !verticals.contains_key(&a.x) {
-                    verticals.insert(a.x, vec![]);
-                }
-                verticals.get_mut(&a.x).unwrap().push(Vertical { min_y: cmp::min(a.y, b.y), max_y: cmp::max(a.y, b.y) })
+            let (a, b) = self.edge(i);
+            if a.x == b.x && a.y != b.y {
+                vertical.push((a.x, cmp::min(a.y, b.y), cmp::max(a.y, b.y), i));
+            } else if a.y == b.y && a.x != b.x {
+                horizontal.push((a.y, cmp::min(a.x, b.x), cmp::max(a.x, b.x), i));
+            } else {
+                errors.push(PolyError::NotAxisAligned { edge: self.edge_vertices(i) });
             }
         }
+        vertical.sort();
+        horizontal.sort();
 
-        for y in 0..grid.height as i32 {
-            let mut count = 0;
-            for x in 0..grid.width as i32 {
-                match verticals.get(&x) {
-                    Some(vs) => {
-                        for v in vs.iter() {
-                            if (2 * v.min_y..2 * v.max_y).contains(&(2 * y + 1)) {
-                                count += 1;
-                            }
-                        }
+        let n = self.contour.len();
+        let adjacent = |i: usize, j: usize| (i + 1) % n == j || (j + 1) % n == i;
+        let mut intersections = vec![];
+        // crossing edges, adjacent ones only share their common vertex
+        for &(y, x1, x2, i) in horizontal.iter() {
+            let start = vertical.partition_point(|&(x, ..)| x < x1);
+            for &(_, y1, y2, j) in vertical[start..].iter().take_while(|&&(x, ..)| x <= x2) {
+                if y1 <= y && y <= y2 && !adjacent(i, j) {
+                    intersections.push((cmp::min(i, j), cmp::max(i, j)));
+                }
+            }
+        }
+        // overlapping or touching collinear edges, adjacent ones may share a vertex
+        for edges in [&vertical, &horizontal].iter() {
+            for (k, &(c, lo, hi, i)) in edges.iter().enumerate() {
+                for &(_, other_lo, other_hi, j) in edges[k + 1..].iter().take_while(|&&(other_c, other_lo, ..)| other_c == c && other_lo <= hi) {
+                    let shared = cmp::min(hi, other_hi) - cmp::max(lo, other_lo);
+                    if shared > 0 || !adjacent(i, j) {
+                        intersections.push((cmp::min(i, j), cmp::max(i, j)));
                     }
-                    None => {}
                 }
+            }
+        }
+        intersections.sort();
+        errors.extend(intersections.into_iter().map(|(i, j)| PolyError::SelfIntersecting { edges: (self.edge_vertices(i), self.edge_vertices(j)) }));
+
+        if errors.is_empty() && self.signed_area() < 0 {
+            errors.push(PolyError::Clockwise);
+        }
+        errors
+    }
+
+    /// Validates the map and the obstacles, which also have to lie strictly
+    /// inside the map without overlapping each other.
+    ///
+    /// Obstacles touching the border are reported as `TouchingMap`; they still
+    /// project fine, which hand-made maps rely on to split the map.
+    pub fn validate_map(map: &Poly, obstacles: &[Poly]) -> Vec<(Contour, PolyError)> {
+        let mut errors = map.validate().into_iter().map(|e| (Contour::Map, e)).collect::<Vec<_>>();
+        let valid = obstacles.iter()
+            .enumerate()
+            .filter(|(i, obstacle)| {
+                let obstacle_errors = obstacle.validate();
+                let valid = obstacle_errors.is_empty();
+                errors.extend(obstacle_errors.into_iter().map(|e| (Contour::Obstacle(*i), e)));
+                valid
+            })
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            return errors;
+        }
+
+        let (bottom_left, top_right) = map.bbox();
+        let size = |min: i32, max: i32| max as i64 - min as i64;
+        if size(bottom_left.x, top_right.x) > MAX_SIZE as i64 || size(bottom_left.y, top_right.y) > MAX_SIZE as i64 {
+            errors.push((Contour::Map, PolyError::TooLarge));
+            return errors;
+        }
+        let mut grid = Grid::new((top_right.x - bottom_left.x) as u32, (top_right.y - bottom_left.y) as u32, GridCell::Void);
+        map.for_each_cell(|p| grid.set(p - bottom_left, GridCell::Free));
+        let index = |p: Point2D| grid.linear_index(p - bottom_left);
+        let is_inside = |p: Point2D| grid.contains(p - bottom_left) && grid[p - bottom_left] == GridCell::Free;
+        // a point is strictly inside the map when the four cells around it are
+        let point_inside = |p: Point2D| {
+            [(-1, -1), (0, -1), (-1, 0), (0, 0)].iter().all(|(dx, dy)| is_inside(p + Point2D::new(*dx, *dy)))
+        };
+
+        let in_closed_bbox = |p: Point2D| p.x >= bottom_left.x && p.x <= top_right.x && p.y >= bottom_left.y && p.y <= top_right.y;
+
+        // index of the obstacle covering every cell
+        let mut owners = vec![NO_OWNER; grid.area()];
+        let mut overlaps = vec![];
+        for (i, obstacle) in valid {
+            let mut outside = false;
+            for edge in 0..obstacle.contour.len() {
+                let (a, b) = obstacle.edge(edge);
+                let d = Point2D::new((b.x - a.x).signum(), (b.y - a.y).signum());
+                // the cell on the left of every unit step is inside the counter-clockwise obstacle
+                let left = match (d.x, d.y) {
+                    (1, 0) => Point2D::new(0, 0),
+                    (-1, 0) => Point2D::new(-1, -1),
+                    (0, 1) => Point2D::new(-1, 0),
+                    _ => Point2D::new(0, -1),
+                };
+                let mut touching = false;
+                let mut edge_outside = !in_closed_bbox(a) || !in_closed_bbox(b);
+                let mut p = a;
+                while !edge_outside && p != b {
+                    edge_outside = !is_inside(p + left);
+                    touching |= !point_inside(p);
+                    p = p + d;
+                }
+                if edge_outside {
+                    errors.push((Contour::Obstacle(i), PolyError::OutsideMap { edge: obstacle.edge_vertices(edge) }));
+                    outside = true;
+                } else if touching {
+                    errors.push((Contour::Obstacle(i), PolyError::TouchingMap { edge: obstacle.edge_vertices(edge) }));
+                }
+            }
+            if outside {
+                continue;
+            }
+            obstacle.for_each_cell(|p| match owners[index(p)] {
+                NO_OWNER => owners[index(p)] = i as u32,
+                j if !overlaps.contains(&(j as usize, i)) => overlaps.push((j as usize, i)),
+                _ => {}
+            });
+        }
+        errors.extend(overlaps.into_iter().map(|(j, i)| (Contour::Obstacle(i), PolyError::Overlapping { obstacle: j })));
+        errors
+    }
+
+    /// Calls `f` with every cell inside the contour.
+    fn for_each_cell<F: FnMut(Point2D)>(&self, mut f: F) {
+        // x of the vertical edges crossing the middle of every row of the bounding box
+        let (bottom_left, top_right) = self.bbox();
+        let mut crossings = vec![vec![]; (top_right.y - bottom_left.y).max(0) as usize];
+        for i in 0..self.contour.len() {
+            let (a, b) = self.edge(i);
+            if a.x == b.x {
+                for y in cmp::min(a.y, b.y)..cmp::max(a.y, b.y) {
+                    crossings[(y - bottom_left.y) as usize].push(a.x);
+                }
+            }
+        }
 
-                if count % 2 > 0 {
-                    grid.set(Point2D::new(x, y), cell);
+        for (dy, xs) in crossings.iter_mut().enumerate() {
+            xs.sort();
+            for pair in xs.chunks(2) {
+                for x in pair[0]..pair[1] {
+                    f(Point2D::new(x, bottom_left.y + dy as i32));
                 }
             }
         }
     }
+
+    /// Traces the counter-clockwise outline of the cells for which `inside`
+    /// holds, starting from the bottom left corner of `start` that has to be
+    /// the leftmost cell of the lowest row. Cells touching at a corner only
+    /// are not connected.
+    pub fn trace<F: Fn(Point2D) -> bool>(start: Point2D, inside: F) -> Poly {
+        let mut contour = vec![start];
+        let mut p = start;
+        let mut d = Point2D::new(1, 0);
+        loop {
+            p = p + d;
+            if p == start {
+                break;
+            }
+            // the cells ahead on the left and on the right of the corner
+            let (left, right) = match (d.x, d.y) {
+                (1, 0) => (p, p + Point2D::new(0, -1)),
+                (0, 1) => (p + Point2D::new(-1, 0), p),
+                (-1, 0) => (p + Point2D::new(-1, -1), p + Point2D::new(-1, 0)),
+                _ => (p + Point2D::new(0, -1), p + Point2D::new(-1, -1)),
+            };
+            let next = if !inside(left) {
+                Point2D::new(-d.y, d.x)
+            } else if inside(right) {
+                Point2D::new(d.y, -d.x)
+            } else {
+                d
+            };
+            if next != d {
+                contour.push(p);
+                d = next;
+            }
+        }
+        Poly::new(contour)
+    }
+
+    pub fn project(&self, grid: &mut Grid, cell: GridCell) {
+        self.for_each_cell(|p| grid.set(p, cell));
+    }
 }
 
-struct Vertical {
-    min_y: i32,
-    max_y: i32,
+#[test]
+fn test_poly_validation() {
+    let poly = |points: &[(i32, i32)]| Poly::new(points.iter().map(|(x, y)| Point2D::new(*x, *y)).collect());
+    let square = |x: i32, y: i32, size: i32| poly(&[(x, y), (x + size, y), (x + size, y + size), (x, y + size)]);
+
+    assert_eq!(square(0, 0, 3).validate(), vec![]);
+    assert_eq!(poly(&[(0, 0), (2, 0), (3, 0), (3, 3), (0, 3)]).validate(), vec![]);
+    assert_eq!(poly(&[(0, 0), (3, 0), (0, 3)]).validate(), vec![PolyError::TooFewVertices]);
+    assert_eq!(poly(&[(0, 0), (3, 0), (3, 3), (1, 2), (0, 3)]).validate(), vec![
+        PolyError::NotAxisAligned { edge: (2, 3) },
+        PolyError::NotAxisAligned { edge: (3, 4) },
+    ]);
+    assert_eq!(poly(&[(0, 0), (0, 3), (3, 3), (3, 0)]).validate(), vec![PolyError::Clockwise]);
+    // a bow tie
+    assert_eq!(
+        poly(&[(0, 0), (2, 0), (2, 1), (1, 1), (1, -1), (0, -1)]).validate(),
+        vec![PolyError::SelfIntersecting { edges: ((0, 1), (3, 4)) }]
+    );
+    // an edge doubling back on the previous one
+    assert_eq!(
+        poly(&[(0, 0), (3, 0), (3, 3), (3, 1), (0, 1)]).validate(),
+        vec![PolyError::SelfIntersecting { edges: ((1, 2), (2, 3)) }, PolyError::SelfIntersecting { edges: ((1, 2), (3, 4)) }]
+    );
+    // two squares touching at a corner
+    assert_eq!(
+        poly(&[(0, 0), (1, 0), (1, 1), (2, 1), (2, 2), (1, 2), (1, 1), (0, 1)]).validate(),
+        [((1, 2), (5, 6)), ((1, 2), (6, 7)), ((2, 3), (5, 6)), ((2, 3), (6, 7))].iter()
+            .map(|edges| PolyError::SelfIntersecting { edges: *edges })
+            .collect::<Vec<_>>()
+    );
+
+    let map = square(0, 0, 10);
+    assert_eq!(Poly::validate_map(&map, &[square(1, 1, 2), square(3, 1, 2)]), vec![]);
+    // touching the top right corner of the map
+    assert_eq!(Poly::validate_map(&map, &[poly(&[(8, 8), (9, 8), (9, 9), (10, 9), (10, 10), (8, 10)])]), vec![
+        (Contour::Obstacle(0), PolyError::TouchingMap { edge: (3, 4) }),
+        (Contour::Obstacle(0), PolyError::TouchingMap { edge: (4, 5) }),
+        (Contour::Obstacle(0), PolyError::TouchingMap { edge: (5, 0) }),
+    ]);
+    assert_eq!(Poly::validate_map(&map, &[square(8, 4, 2)]).len(), 2);
+    assert_eq!(Poly::validate_map(&map, &[square(-5, 2, 2)]).len(), 4);
+    // crossing a notch of the map
+    let notched = poly(&[(0, 0), (10, 0), (10, 10), (6, 10), (6, 5), (5, 5), (5, 10), (0, 10)]);
+    assert_eq!(Poly::validate_map(&notched, &[square(4, 6, 3)]), vec![
+        (Contour::Obstacle(0), PolyError::OutsideMap { edge: (0, 1) }),
+        (Contour::Obstacle(0), PolyError::OutsideMap { edge: (2, 3) }),
+    ]);
+    assert_eq!(
+        Poly::validate_map(&map, &[square(1, 1, 3), square(5, 5, 2), square(2, 2, 3), square(1, 1, 1)]),
+        vec![
+            (Contour::Obstacle(2), PolyError::Overlapping { obstacle: 0 }),
+            (Contour::Obstacle(3), PolyError::Overlapping { obstacle: 0 }),
+        ]
+    );
+    assert_eq!(
+        Poly::validate_map(&square(0, 0, 2), &[poly(&[(0, 0), (1, 0), (0, 1)])]),
+        vec![(Contour::Obstacle(0), PolyError::TooFewVertices)]
+    );
 }
+
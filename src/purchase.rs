@@ -0,0 +1,112 @@
+use crate::solution::ParseError;
+use crate::state::BoosterType;
+
+pub struct Purchase;
+
+impl Purchase {
+    /// Parses a `.buy` file, e.g. `BBF`, into the boosters bought for a map.
+    pub fn parse(s: &str) -> Result<Vec<BoosterType>, ParseError> {
+        s.trim_end().bytes().enumerate()
+            .map(|(offset, c)| match c {
+                b'B' => Ok(BoosterType::B),
+                b'F' => Ok(BoosterType::F),
+                b'L' => Ok(BoosterType::L),
+                b'R' => Ok(BoosterType::R),
+                b'C' => Ok(BoosterType::C),
+                _ => Err(ParseError { offset, message: format!("unexpected booster '{}'", c as char) }),
+            })
+            .collect()
+    }
+
+    pub fn write(boosters: &[BoosterType]) -> String {
+        boosters.iter().map(|type_| format!("{:?}", type_)).collect()
+    }
+
+    /// Price in LAM coins.
+    pub fn cost(type_: BoosterType) -> u32 {
+        match type_ {
+            BoosterType::B => 1000,
+            BoosterType::F => 300,
+            BoosterType::L => 700,
+            BoosterType::R => 1200,
+            BoosterType::C => 2000,
+            BoosterType::X => panic!("spawn points can't be bought"),
+        }
+    }
+
+    /// Expected score gain of a map when buying speeds the solver up from
+    /// `solver_time` to `solver_time_with`.
+    ///
+    /// A map is worth `max_points * best_time / time`, the speed-up is assumed
+    /// to carry over from the solver to the `baseline` time of our submission.
+    pub fn estimate_gain(max_points: u32, baseline: u32, solver_time: u32, solver_time_with: u32) -> f64 {
+        let predicted = baseline as f64 * solver_time_with as f64 / solver_time as f64;
+        (max_points as f64 * (baseline as f64 / predicted - 1.0)).max(0.0)
+    }
+
+    /// Picks at most one option per map maximising the total gain within the
+    /// budget, `options[map]` being `(booster, gain)` pairs.
+    pub fn plan(options: &[Vec<(BoosterType, f64)>], budget: u32) -> Vec<Option<BoosterType>> {
+        // all the prices are multiples of 100 coins
+        let unit = 100;
+        let capacity = (budget / unit) as usize;
+        let mut best = vec![vec![0.0; capacity + 1]; options.len() + 1];
+        for (i, map_options) in options.iter().enumerate() {
+            for c in 0..=capacity {
+                best[i + 1][c] = best[i][c];
+                for (type_, gain) in map_options.iter() {
+                    let cost = (Purchase::cost(*type_) / unit) as usize;
+                    if cost <= c && best[i][c - cost] + gain > best[i + 1][c] {
+                        best[i + 1][c] = best[i][c - cost] + gain;
+                    }
+                }
+            }
+        }
+
+        let mut result = vec![None; options.len()];
+        let mut c = capacity;
+        for i in (0..options.len()).rev() {
+            if best[i + 1][c] == best[i][c] {
+                continue;
+            }
+            let (type_, _) = options[i].iter()
+                .find(|(type_, gain)| {
+                    let cost = (Purchase::cost(*type_) / unit) as usize;
+                    cost <= c && best[i][c - cost] + gain == best[i + 1][c]
+                })
+                .unwrap();
+            result[i] = Some(*type_);
+            c -= (Purchase::cost(*type_) / unit) as usize;
+        }
+        result
+    }
+}
+
+#[test]
+fn test_purchase_parsing() {
+    assert_eq!(Purchase::parse("BBF\n"), Ok(vec![BoosterType::B, BoosterType::B, BoosterType::F]));
+    assert_eq!(Purchase::parse(""), Ok(vec![]));
+    assert_eq!(Purchase::parse("CX").unwrap_err().offset, 1);
+    assert_eq!(Purchase::parse("B F").unwrap_err().offset, 1);
+    assert_eq!(Purchase::write(&Purchase::parse("LRCBF").unwrap()), "LRCBF");
+}
+
+#[test]
+fn test_purchase_plan() {
+    assert_eq!(Purchase::estimate_gain(1000, 100, 200, 100), 1000.0);
+    assert_eq!(Purchase::estimate_gain(1000, 100, 200, 250), 0.0);
+
+    let options = vec![
+        vec![(BoosterType::C, 700.0), (BoosterType::F, 100.0)],
+        vec![(BoosterType::B, 300.0)],
+        vec![],
+        vec![(BoosterType::F, 250.0), (BoosterType::L, 260.0)],
+    ];
+    assert_eq!(Purchase::plan(&options, 0), vec![None, None, None, None]);
+    assert_eq!(Purchase::plan(&options, 1999), vec![Some(BoosterType::F), Some(BoosterType::B), None, Some(BoosterType::F)]);
+    assert_eq!(Purchase::plan(&options, 2000), vec![Some(BoosterType::C), None, None, None]);
+    assert_eq!(
+        Purchase::plan(&options, 4000),
+        vec![Some(BoosterType::C), Some(BoosterType::B), None, Some(BoosterType::L)]
+    );
+}
@@ -0,0 +1,622 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::geometry::Point2D;
+use crate::grid::{Grid, GridCell, NEIGHBOURS_4, NEIGHBOURS_8};
+use crate::inventory::Inventory;
+use crate::problem::{ExportError, Poly, Problem, Task};
+use crate::solution::{ParseError, Parser};
+use crate::state::{Booster, BoosterType, State};
+
+static BOOSTER_TYPES: [BoosterType; 6] = [
+    BoosterType::B, BoosterType::F, BoosterType::L, BoosterType::R, BoosterType::C, BoosterType::X,
+];
+
+/// The constraints of a block puzzle, read from a `puzzle.cond` file.
+#[derive(PartialEq, Eq, Debug)]
+pub struct Puzzle {
+    pub block: u32,
+    pub epoch: u32,
+    /// The map has to fit in a `size`x`size` square.
+    pub size: u32,
+    pub min_vertices: u32,
+    pub max_vertices: u32,
+    boosters: [u32; 6],
+    /// Cells that have to be inside the map.
+    pub include: Vec<Point2D>,
+    /// Cells that have to be outside of the map.
+    pub exclude: Vec<Point2D>,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum GenerateError {
+    Conflicting { cell: Point2D },
+    OutsideSquare { cell: Point2D },
+    NoFullLine,
+    Unreachable { cell: Point2D },
+    BelowMinArea { cell: Point2D },
+    TooFewVertices { count: u32 },
+    TooManyVertices { count: u32 },
+    TooSmall,
+    Export(ExportError),
+    /// The generated map fails some constraints, with what is wrong.
+    Unsatisfied { failures: Vec<(Constraint, String)> },
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenerateError::Conflicting { cell } => write!(f, "({},{}) is both included and excluded", cell.x, cell.y),
+            GenerateError::OutsideSquare { cell } => write!(f, "({},{}) is included but outside of the square", cell.x, cell.y),
+            GenerateError::NoFullLine => write!(f, "every row and column has an excluded cell"),
+            GenerateError::Unreachable { cell } => write!(f, "can't connect ({},{}) to the map", cell.x, cell.y),
+            GenerateError::BelowMinArea { cell } => write!(f, "carving out ({},{}) leaves too few cells", cell.x, cell.y),
+            GenerateError::TooFewVertices { count } => write!(f, "stuck at {} vertices, too few", count),
+            GenerateError::TooManyVertices { count } => write!(f, "stuck at {} vertices, too many", count),
+            GenerateError::TooSmall => write!(f, "not enough room for the robot and the boosters"),
+            GenerateError::Export(error) => write!(f, "{}", error),
+            GenerateError::Unsatisfied { failures } => {
+                let failures = failures.iter().map(|(constraint, error)| format!("{}: {}", constraint, error)).collect::<Vec<_>>();
+                write!(f, "the map fails {}", failures.join("; "))
+            }
+        }
+    }
+}
+
+/// The rules a puzzle solution is checked against.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Constraint {
+    Polygon,
+    Vertices,
+    BoundingBox,
+    Area,
+    Include,
+    Exclude,
+    Boosters,
+    Placement,
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Constraint::Polygon => write!(f, "polygon"),
+            Constraint::Vertices => write!(f, "vertex count"),
+            Constraint::BoundingBox => write!(f, "bounding box"),
+            Constraint::Area => write!(f, "area"),
+            Constraint::Include => write!(f, "included cells"),
+            Constraint::Exclude => write!(f, "excluded cells"),
+            Constraint::Boosters => write!(f, "booster counts"),
+            Constraint::Placement => write!(f, "booster placement"),
+        }
+    }
+}
+
+impl Puzzle {
+    /// Parses `block,epoch,size,vMin,vMax,B,F,L,R,C,X#include#exclude`.
+    pub fn parse(s: &str) -> Result<Puzzle, ParseError> {
+        let mut parser = Parser::new(s.trim_end());
+        let mut params = [0; 11];
+        for (i, param) in params.iter_mut().enumerate() {
+            if i > 0 {
+                parser.expect(b',')?;
+            }
+            let start = parser.offset;
+            *param = parser.number()?;
+            if *param < 0 {
+                return Err(parser.error_at(start, "expected a non-negative number".to_string()));
+            }
+        }
+        parser.expect(b'#')?;
+        let include = Self::parse_points(&mut parser)?;
+        parser.expect(b'#')?;
+        let exclude = Self::parse_points(&mut parser)?;
+        if parser.peek().is_some() {
+            return Err(parser.error_at(parser.offset, "expected the end of the puzzle".to_string()));
+        }
+
+        let [block, epoch, size, min_vertices, max_vertices, b, f, l, r, c, x] = params;
+        let mut boosters = [0; 6];
+        for (type_, count) in BOOSTER_TYPES.iter().zip([b, f, l, r, c, x].iter()) {
+            boosters[*type_ as usize] = *count as u32;
+        }
+        Ok(Puzzle {
+            block: block as u32,
+            epoch: epoch as u32,
+            size: size as u32,
+            min_vertices: min_vertices as u32,
+            max_vertices: max_vertices as u32,
+            boosters,
+            include,
+            exclude,
+        })
+    }
+
+    fn parse_points(parser: &mut Parser) -> Result<Vec<Point2D>, ParseError> {
+        let mut points = vec![];
+        if parser.peek() == Some(b'(') {
+            points.push(parser.point()?);
+            while parser.peek() == Some(b',') {
+                parser.next_byte();
+                points.push(parser.point()?);
+            }
+        }
+        Ok(points)
+    }
+
+    /// How many boosters of a type the map has to contain.
+    pub fn booster_count(&self, type_: BoosterType) -> u32 {
+        self.boosters[type_ as usize]
+    }
+
+    /// Smallest allowed area, 20% of the square.
+    pub fn min_area(&self) -> u32 {
+        (self.size * self.size).div_ceil(5)
+    }
+
+    /// Smallest allowed width or height of the bounding box.
+    pub fn min_extent(&self) -> u32 {
+        self.size - self.size / 10
+    }
+
+    /// Checks a task against every constraint of the puzzle, failures come
+    /// with a description of what is wrong.
+    pub fn check(&self, task: &Task) -> Vec<(Constraint, Result<(), String>)> {
+        let mut results = vec![];
+        let mut problems = Poly::validate_map(&task.map, &task.obstacles).iter()
+            .map(|(contour, error)| format!("{}: {}", contour, error))
+            .collect::<Vec<_>>();
+        if !task.obstacles.is_empty() {
+            problems.push("a puzzle solution can't have obstacles".to_string());
+        }
+        let valid = problems.is_empty();
+        results.push((Constraint::Polygon, Self::outcome(problems)));
+
+        let vertices = task.map.contour.len() as u32;
+        results.push((Constraint::Vertices, if vertices < self.min_vertices || vertices > self.max_vertices {
+            Err(format!("{} vertices, expected {} to {}", vertices, self.min_vertices, self.max_vertices))
+        } else {
+            Ok(())
+        }));
+
+        let (bottom_left, top_right) = task.map.bbox();
+        let size = self.size as i32;
+        let mut problems = vec![];
+        if bottom_left.x < 0 || bottom_left.y < 0 || top_right.x > size || top_right.y > size {
+            problems.push(format!("({},{})-({},{}) doesn't fit in the {}x{} square",
+                                  bottom_left.x, bottom_left.y, top_right.x, top_right.y, size, size));
+        }
+        let extent = (top_right.x - bottom_left.x).max(top_right.y - bottom_left.y);
+        if extent < self.min_extent() as i32 {
+            problems.push(format!("{} wide at most, expected at least {}", extent, self.min_extent()));
+        }
+        results.push((Constraint::BoundingBox, Self::outcome(problems)));
+
+        // the rest needs cells, the map is projected onto a grid of its bounding box
+        if !valid {
+            let invalid = || Err("the map isn't a valid polygon".to_string());
+            results.push((Constraint::Area, invalid()));
+            results.push((Constraint::Include, invalid()));
+            results.push((Constraint::Exclude, invalid()));
+            results.push((Constraint::Boosters, self.check_booster_counts(task)));
+            results.push((Constraint::Placement, invalid()));
+            return results;
+        }
+        let mut map = Poly::new(task.map.contour.clone());
+        map.translate(-bottom_left);
+        let mut grid = Grid::new((top_right.x - bottom_left.x) as u32, (top_right.y - bottom_left.y) as u32, GridCell::Void);
+        map.project(&mut grid, GridCell::Free);
+        let inside = |p: Point2D| grid.contains(p - bottom_left) && grid[p - bottom_left] == GridCell::Free;
+
+        let area = grid.cells_of(GridCell::Free).count() as u32;
+        results.push((Constraint::Area, if area < self.min_area() {
+            Err(format!("{} cells, expected at least {} (20% of {}x{})", area, self.min_area(), size, size))
+        } else {
+            Ok(())
+        }));
+
+        let write = |p: &Point2D| format!("({},{})", p.x, p.y);
+        let outside = self.include.iter().filter(|p| !inside(**p)).map(write).collect::<Vec<_>>();
+        results.push((Constraint::Include, Self::outcome(outside.into_iter().map(|p| format!("{} is outside of the map", p)).collect())));
+        let inside_cells = self.exclude.iter().filter(|p| inside(**p)).map(write).collect::<Vec<_>>();
+        results.push((Constraint::Exclude, Self::outcome(inside_cells.into_iter().map(|p| format!("{} is inside the map", p)).collect())));
+
+        results.push((Constraint::Boosters, self.check_booster_counts(task)));
+
+        // the robot and the boosters are on the map, no two boosters on the same cell
+        let mut problems = vec![];
+        let mut taken = vec![];
+        if !inside(task.initial_position) {
+            problems.push(format!("the robot at {} is outside of the map", write(&task.initial_position)));
+        }
+        for booster in task.boosters.iter() {
+            if !inside(booster.position) {
+                problems.push(format!("{:?} at {} is outside of the map", booster.type_, write(&booster.position)));
+            } else if taken.contains(&booster.position) {
+                problems.push(format!("{:?} at {} shares its cell", booster.type_, write(&booster.position)));
+            }
+            taken.push(booster.position);
+        }
+        results.push((Constraint::Placement, Self::outcome(problems)));
+        results
+    }
+
+    fn check_booster_counts(&self, task: &Task) -> Result<(), String> {
+        let problems = BOOSTER_TYPES.iter()
+            .filter_map(|type_| {
+                let count = task.boosters.iter().filter(|b| b.type_ == *type_).count() as u32;
+                if count == self.booster_count(*type_) {
+                    None
+                } else {
+                    Some(format!("{} {:?} instead of {}", count, type_, self.booster_count(*type_)))
+                }
+            })
+            .collect();
+        Self::outcome(problems)
+    }
+
+    fn outcome(problems: Vec<String>) -> Result<(), String> {
+        if problems.is_empty() { Ok(()) } else { Err(problems.join(", ")) }
+    }
+
+    /// Makes the `.desc` of a map satisfying the puzzle.
+    ///
+    /// Starts from the whole square, carves a corridor from the border to
+    /// every excluded cell, then adds or removes cells along the border until
+    /// the number of vertices is in range. Every step keeps the map a single
+    /// polygon without holes. The result is checked against the puzzle
+    /// before it's returned.
+    pub fn generate(&self) -> Result<String, GenerateError> {
+        if let Some(cell) = self.exclude.iter().find(|p| self.include.contains(p)) {
+            return Err(GenerateError::Conflicting { cell: *cell });
+        }
+        let square = Grid::new(self.size, self.size, GridCell::Free);
+        if let Some(cell) = self.include.iter().find(|p| !square.contains(**p)) {
+            return Err(GenerateError::OutsideSquare { cell: *cell });
+        }
+        let mut shape = Shape::new(self)?;
+        for cell in self.exclude.iter() {
+            shape.carve(*cell)?;
+        }
+        shape.adjust_vertices(self.min_vertices, self.max_vertices)?;
+
+        let cells = shape.grid.free_cells().collect::<Vec<_>>();
+        let count = BOOSTER_TYPES.iter().map(|type_| self.booster_count(*type_) as usize).sum::<usize>();
+        if cells.len() < count + 1 {
+            return Err(GenerateError::TooSmall);
+        }
+        // spread the boosters evenly over the map, row by row
+        let mut positions = (0..=count).map(|i| cells[i * (cells.len() - 1) / count.max(1)]);
+        let initial_position = positions.next().unwrap();
+        let mut boosters = vec![];
+        for type_ in BOOSTER_TYPES.iter() {
+            for _ in 0..self.booster_count(*type_) {
+                boosters.push(Booster { type_: *type_, position: positions.next().unwrap() });
+            }
+        }
+
+        let state = State::new(shape.grid, boosters, initial_position, Inventory::new());
+        let desc = Problem::write(&state).map_err(GenerateError::Export)?;
+        let task = Problem::parse_task(&desc).expect("a written map parses");
+        let failures = self.check(&task).into_iter()
+            .filter_map(|(constraint, result)| result.err().map(|e| (constraint, e)))
+            .collect::<Vec<_>>();
+        if !failures.is_empty() {
+            return Err(GenerateError::Unsatisfied { failures });
+        }
+        Ok(desc)
+    }
+}
+
+/// A set of cells of the puzzle square that stays 4-connected, without holes
+/// and without cells touching only at a corner, so that its outline is a
+/// simple polygon.
+struct Shape {
+    size: i32,
+    // Free cells are inside, Void ones outside
+    grid: Grid,
+    // include cells and the cells keeping the bounding box wide enough
+    protected: Vec<bool>,
+    // Void where the puzzle excludes a cell
+    allowed: Grid,
+    min_area: u32,
+    vertices: u32,
+}
+
+impl Shape {
+    fn new(puzzle: &Puzzle) -> Result<Shape, GenerateError> {
+        let size = puzzle.size as i32;
+        let mut shape = Shape {
+            size,
+            grid: Grid::new(puzzle.size, puzzle.size, GridCell::Free),
+            protected: vec![false; (size * size) as usize],
+            allowed: Grid::new(puzzle.size, puzzle.size, GridCell::Free),
+            min_area: puzzle.min_area(),
+            vertices: 4,
+        };
+        for p in puzzle.include.iter() {
+            let i = shape.grid.linear_index(*p);
+            shape.protected[i] = true;
+        }
+        for p in puzzle.exclude.iter() {
+            if shape.allowed.contains(*p) {
+                shape.allowed.set(*p, GridCell::Void);
+            }
+        }
+        // a full row or column keeps the bounding box as wide as the square
+        let middle_out = (0..size).map(|d| if d % 2 == 0 { size / 2 + d / 2 } else { size / 2 - d / 2 - 1 });
+        let allowed = &shape.allowed;
+        let row = middle_out.clone().find(|y| allowed.row(*y).all(|c| c == GridCell::Free));
+        let line = match row {
+            Some(y) => (0..size).map(|x| Point2D::new(x, y)).collect::<Vec<_>>(),
+            None => {
+                let column = middle_out.clone().find(|x| allowed.column(*x).all(|c| c == GridCell::Free));
+                let x = column.ok_or(GenerateError::NoFullLine)?;
+                (0..size).map(|y| Point2D::new(x, y)).collect()
+            }
+        };
+        for p in line {
+            let i = shape.grid.linear_index(p);
+            shape.protected[i] = true;
+        }
+        Ok(shape)
+    }
+
+    fn is_inside(&self, p: Point2D) -> bool {
+        self.grid.contains(p) && self.grid[p] == GridCell::Free
+    }
+
+    /// Whether adding or removing `p` keeps the shape a simple polygon.
+    fn can_toggle(&self, p: Point2D) -> bool {
+        let adding = !self.is_inside(p);
+        let ring = NEIGHBOURS_8.iter().map(|d| self.is_inside(p + *d)).collect::<Vec<_>>();
+        // cells touching only at a corner
+        for k in (1..8).step_by(2) {
+            if ring[k - 1] == ring[(k + 1) % 8] && ring[k - 1] != ring[k] && ring[k] == adding {
+                return false;
+            }
+        }
+        // a single run of inside cells around keeps both sides connected
+        (0..8).filter(|k| ring[*k] && !ring[(k + 7) % 8]).count() == 1
+    }
+
+    /// Change in the number of vertices when toggling `p`.
+    fn vertex_delta(&self, p: Point2D) -> i32 {
+        let ring = NEIGHBOURS_8.iter().map(|d| self.is_inside(p + *d)).collect::<Vec<_>>();
+        let mut delta = 0;
+        // every corner of `p` is shared with a corner neighbour and the two sides next to it
+        for k in (1..8).step_by(2) {
+            let count = (self.is_inside(p) as i32) + (k - 1..=k + 1).filter(|i| ring[i % 8]).count() as i32;
+            let after = if self.is_inside(p) { count - 1 } else { count + 1 };
+            let is_vertex = |count: i32| count == 1 || count == 3;
+            delta += is_vertex(after) as i32 - is_vertex(count) as i32;
+        }
+        delta
+    }
+
+    fn toggle(&mut self, p: Point2D) {
+        self.vertices = (self.vertices as i32 + self.vertex_delta(p)) as u32;
+        if self.is_inside(p) {
+            self.grid.set(p, GridCell::Void);
+        } else {
+            self.grid.set(p, GridCell::Free);
+        }
+    }
+
+    /// Removes `target` and the cells between it and the outside of the square.
+    fn carve(&mut self, target: Point2D) -> Result<(), GenerateError> {
+        if !self.is_inside(target) {
+            return Ok(());
+        }
+        // straight corridors first, they add the fewest vertices
+        let mut corridors = NEIGHBOURS_4.iter()
+            .map(|d| {
+                let mut corridor = vec![target];
+                while self.is_inside(*corridor.last().unwrap() + *d) {
+                    corridor.push(*corridor.last().unwrap() + *d);
+                }
+                corridor
+            })
+            .collect::<Vec<_>>();
+        corridors.sort_by_key(|c| c.len());
+        if !corridors.iter().any(|corridor| self.remove_all(corridor).is_ok()) {
+            self.carve_around(target)?;
+        }
+        if self.grid.num_free < self.min_area {
+            return Err(GenerateError::BelowMinArea { cell: target });
+        }
+        Ok(())
+    }
+
+    /// Removes the shortest corridor to `target` that avoids the cells that
+    /// can't be removed.
+    fn carve_around(&mut self, target: Point2D) -> Result<(), GenerateError> {
+        let mut blocked = vec![false; self.grid.area()];
+        loop {
+            let corridor = self.shortest_corridor(target, &blocked).ok_or(GenerateError::Unreachable { cell: target })?;
+            match self.remove_all(&corridor) {
+                Ok(()) => return Ok(()),
+                Err(p) if p == target => return Err(GenerateError::Unreachable { cell: target }),
+                // look for a way around the cell that couldn't be removed
+                Err(p) => blocked[self.grid.linear_index(p)] = true,
+            }
+        }
+    }
+
+    /// Removes the cells of a corridor from its outer end, all or nothing,
+    /// returns the first cell that can't be removed.
+    fn remove_all(&mut self, corridor: &[Point2D]) -> Result<(), Point2D> {
+        for (i, p) in corridor.iter().enumerate().rev() {
+            if self.protected[self.grid.linear_index(*p)] || !self.can_toggle(*p) {
+                corridor[i + 1..].iter().for_each(|p| self.toggle(*p));
+                return Err(*p);
+            }
+            self.toggle(*p);
+        }
+        Ok(())
+    }
+
+    /// Breadth-first search from `target` to the outside through cells that
+    /// can be removed, the outer end last.
+    fn shortest_corridor(&self, target: Point2D, blocked: &[bool]) -> Option<Vec<Point2D>> {
+        let mut parent = vec![None; self.grid.area()];
+        let mut queue = VecDeque::new();
+        parent[self.grid.linear_index(target)] = Some(target);
+        queue.push_back(target);
+        while let Some(p) = queue.pop_front() {
+            for d in NEIGHBOURS_4.iter() {
+                let q = p + *d;
+                if !self.is_inside(q) {
+                    let mut corridor = vec![p];
+                    while *corridor.last().unwrap() != target {
+                        corridor.push(parent[self.grid.linear_index(*corridor.last().unwrap())].unwrap());
+                    }
+                    corridor.reverse();
+                    return Some(corridor);
+                }
+                let i = self.grid.linear_index(q);
+                if parent[i].is_none() && !blocked[i] && !self.protected[i] {
+                    parent[i] = Some(p);
+                    queue.push_back(q);
+                }
+            }
+        }
+        None
+    }
+
+    /// Toggles cells until the number of vertices is within range, dents and
+    /// bumps add vertices while filling them removes some.
+    fn adjust_vertices(&mut self, min_vertices: u32, max_vertices: u32) -> Result<(), GenerateError> {
+        while self.vertices < min_vertices || self.vertices > max_vertices {
+            let growing = self.vertices < min_vertices;
+            let before = self.vertices;
+            for y in 0..self.size {
+                for x in 0..self.size {
+                    let p = Point2D::new(x, y);
+                    let i = self.grid.linear_index(p);
+                    let allowed = if self.is_inside(p) {
+                        !self.protected[i] && self.grid.num_free > self.min_area
+                    } else {
+                        self.allowed[p] == GridCell::Free
+                    };
+                    let delta = self.vertex_delta(p);
+                    let wanted = if growing { delta > 0 } else { delta < 0 };
+                    if allowed && wanted && self.can_toggle(p) {
+                        self.toggle(p);
+                        if self.vertices >= min_vertices && self.vertices <= max_vertices {
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+            if self.vertices == before {
+                return Err(if growing {
+                    GenerateError::TooFewVertices { count: self.vertices }
+                } else {
+                    GenerateError::TooManyVertices { count: self.vertices }
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_puzzle_parsing() {
+    let puzzle = Puzzle::parse("1,1,150,400,1200,6,10,5,1,3,4#(73,61),(49,125)#(92,1)\n").unwrap();
+    assert_eq!((puzzle.block, puzzle.epoch, puzzle.size), (1, 1, 150));
+    assert_eq!((puzzle.min_vertices, puzzle.max_vertices), (400, 1200));
+    assert_eq!(puzzle.booster_count(BoosterType::B), 6);
+    assert_eq!(puzzle.booster_count(BoosterType::X), 4);
+    assert_eq!(puzzle.include, vec![Point2D::new(73, 61), Point2D::new(49, 125)]);
+    assert_eq!(puzzle.exclude, vec![Point2D::new(92, 1)]);
+    assert_eq!((puzzle.min_area(), puzzle.min_extent()), (4500, 135));
+    assert_eq!(Puzzle::parse("1,1,10,4,8,0,0,0,0,0,0##").unwrap().include, vec![]);
+    assert_eq!(Puzzle::parse("1,1,10,4,8,0,0,0,0,0#").unwrap_err().offset, 20);
+    assert_eq!(Puzzle::parse("1,1,-10,4,8,0,0,0,0,0,0##").unwrap_err().offset, 4);
+    assert_eq!(Puzzle::parse("1,1,10,4,8,0,0,0,0,0,0#(1,1)#(2,2)x").unwrap_err().offset, 34);
+}
+
+#[test]
+fn test_generate() {
+    let check = |cond: &str| {
+        let puzzle = Puzzle::parse(cond).unwrap();
+        let desc = puzzle.generate().unwrap();
+        for (constraint, result) in puzzle.check(&Problem::parse_task(&desc).unwrap()) {
+            assert_eq!(result, Ok(()), "{}", constraint);
+        }
+    };
+    check("1,1,10,4,12,1,0,0,0,1,1#(1,1),(8,8)#(5,5),(0,9)");
+    check("1,1,50,150,300,2,2,2,2,2,2#(10,10),(40,40),(25,2)#(25,25),(25,26),(26,25),(0,0),(49,49),(24,1),(26,1),(25,0)");
+    check("1,1,30,4,4,0,0,0,0,0,0##");
+    // every row has an excluded cell, a full column keeps the bounding box instead
+    check("1,1,10,4,8,0,0,0,0,0,0##(0,0),(1,1),(0,2),(1,3),(0,4),(1,5),(0,6),(1,7),(0,8),(1,9)");
+    let conflicting = Puzzle::parse("1,1,10,4,12,0,0,0,0,0,0#(3,3)#(3,3)").unwrap();
+    assert_eq!(conflicting.generate().err(), Some(GenerateError::Conflicting { cell: Point2D::new(3, 3) }));
+    let outside = Puzzle::parse("1,1,10,4,12,0,0,0,0,0,0#(3,3),(10,3)#").unwrap();
+    assert_eq!(outside.generate().err(), Some(GenerateError::OutsideSquare { cell: Point2D::new(10, 3) }));
+    let diagonal = (0..10).map(|i| format!("({},{})", i, i)).collect::<Vec<_>>().join(",");
+    let no_line = Puzzle::parse(&format!("1,1,10,4,40,0,0,0,0,0,0##{}", diagonal)).unwrap();
+    assert_eq!(no_line.generate().err(), Some(GenerateError::NoFullLine));
+    // only the middle row is left, half of the minimum area, carved from the borders in
+    let rows = [9, 8, 7, 6, 0, 1, 2, 3, 4].iter().flat_map(|y| (0..10).map(move |x| (x, *y)))
+        .map(|(x, y)| format!("({},{})", x, y)).collect::<Vec<_>>().join(",");
+    let too_small = Puzzle::parse(&format!("1,1,10,4,40,0,0,0,0,0,0##{}", rows)).unwrap();
+    match too_small.generate() {
+        Err(GenerateError::BelowMinArea { .. }) => {}
+        result => panic!("unexpected {:?}", result),
+    }
+
+    // pseudo-random cells like the ones of the contest blocks
+    let mut seed = 12345u32;
+    let mut random = |n: u32| {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        (seed >> 8) % n
+    };
+    for size in [50, 100, 200].iter() {
+        let mut points = vec![];
+        while points.len() < (size / 5) as usize {
+            let p = Point2D::new(random(*size) as i32, random(*size) as i32);
+            if !points.contains(&p) {
+                points.push(p);
+            }
+        }
+        let write = |points: &[Point2D]| points.iter().map(|p| format!("({},{})", p.x, p.y)).collect::<Vec<_>>().join(",");
+        let (include, exclude) = points.split_at(points.len() / 2);
+        check(&format!("1,1,{},{},{},4,6,2,1,2,3#{}#{}", size, size, size * 3, write(include), write(exclude)));
+    }
+}
+
+#[test]
+fn test_check_puzzle() {
+    let puzzle = Puzzle::parse("1,1,10,4,6,1,0,0,0,0,1#(1,1),(8,8)#(5,5)").unwrap();
+    let failures = |desc: &str| {
+        puzzle.check(&Problem::parse_task(desc).unwrap()).into_iter()
+            .filter_map(|(constraint, result)| result.err().map(|e| (constraint, e)))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(failures("(0,0),(10,0),(10,5),(5,5),(5,6),(0,6)#(0,0)##B(1,3);X(9,1)"), vec![
+        (Constraint::Include, "(8,8) is outside of the map".to_string()),
+    ]);
+    assert_eq!(failures("(0,0),(10,0),(10,10),(0,10)#(0,0)##B(1,3);X(1,3);L(12,3)"), vec![
+        (Constraint::Exclude, "(5,5) is inside the map".to_string()),
+        (Constraint::Boosters, "1 L instead of 0".to_string()),
+        (Constraint::Placement, "X at (1,3) shares its cell, L at (12,3) is outside of the map".to_string()),
+    ]);
+    assert_eq!(failures("(0,0),(10,0),(10,1),(0,1)#(0,0)##B(1,0);X(9,0)"), vec![
+        (Constraint::Area, "10 cells, expected at least 20 (20% of 10x10)".to_string()),
+        (Constraint::Include, "(1,1) is outside of the map, (8,8) is outside of the map".to_string()),
+    ]);
+    // like the official checker, boosters may be next to the robot or on its cell but not share one
+    let map = "(0,0),(10,0),(10,10),(6,10),(6,5),(0,5)#(2,2)##";
+    for boosters in ["B(3,3);X(9,9)", "B(2,3);X(9,9)", "B(2,2);X(9,9)", "B(8,9);X(9,9)"].iter() {
+        assert_eq!(failures(&format!("{}{}", map, boosters)), vec![]);
+    }
+    assert_eq!(failures(&format!("{}B(9,9);X(9,9)", map)), vec![
+        (Constraint::Placement, "X at (9,9) shares its cell".to_string()),
+    ]);
+    let result = failures("(0,0),(11,0),(11,2),(1,2),(1,1),(0,1),(0,10),(-1,10)#(0,0)#(2,2),(3,2),(3,3),(2,3)#");
+    assert_eq!(result.iter().map(|(c, _)| *c).collect::<Vec<_>>(), vec![
+        Constraint::Polygon, Constraint::Vertices, Constraint::BoundingBox, Constraint::Area,
+        Constraint::Include, Constraint::Exclude, Constraint::Boosters, Constraint::Placement,
+    ]);
+}
@@ -0,0 +1,322 @@
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use crate::geometry::Point2D;
+use crate::grid::{Grid, GridCell, NEIGHBOURS_4, NEIGHBOURS_8};
+
+const NONE: u32 = u32::MAX;
+// cells per chunk of labels
+const CHUNK: usize = 4096;
+
+/// Largest part cut off from a region that `Regions::remove` looks for.
+pub const MAX_CUT_OFF: usize = 1024;
+
+/// Undo record of a `Regions` update, replayed backwards by `Regions::undo`.
+#[derive(Clone, Debug)]
+pub enum RegionChange {
+    Removed { cell: usize, label: u32 },
+    Split { from: u32, label: u32, cells: Vec<usize> },
+}
+
+/// Connected regions of the Free cells of a grid.
+///
+/// Wrapping a cell usually can't disconnect its region, which is checked by
+/// looking at the 8 cells around it. Otherwise searches are run from every
+/// side of the cell in lockstep, and a part is cut off once all the searches
+/// inside it ran out of cells. The searches give up after `MAX_CUT_OFF`
+/// steps, so wrapping a cell visits at most `4 * MAX_CUT_OFF` cells, and a
+/// larger part that got cut off keeps the label of its region. A region is
+/// then a union of 4-connected components, every part split off is a single
+/// one.
+///
+/// Labels are stored in chunks that clones share until one of them writes
+/// to it, so that cloning a `State` doesn't copy a label per cell.
+#[derive(Clone)]
+pub struct Regions {
+    labels: Vec<Rc<Vec<u32>>>,
+    sizes: Vec<u32>,
+}
+
+impl Regions {
+    pub fn new(grid: &Grid) -> Regions {
+        let area = grid.area();
+        let mut regions = Regions {
+            labels: (0..area).step_by(CHUNK).map(|start| Rc::new(vec![NONE; CHUNK.min(area - start)])).collect(),
+            sizes: vec![],
+        };
+        for p in grid.free_cells().collect::<Vec<_>>() {
+            if regions.get(grid.linear_index(p)) != NONE {
+                continue;
+            }
+            let label = regions.sizes.len() as u32;
+            let size = regions.flood(grid, p, NONE, label);
+            regions.sizes.push(size);
+        }
+        regions
+    }
+
+    pub fn label(&self, grid: &Grid, p: Point2D) -> Option<u32> {
+        Some(self.get(grid.linear_index(p))).filter(|l| *l != NONE)
+    }
+
+    /// Labels of the regions that still have Free cells.
+    pub fn labels(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.sizes.len() as u32).filter(move |l| self.sizes[*l as usize] > 0)
+    }
+
+    pub fn count(&self) -> usize {
+        self.labels().count()
+    }
+
+    pub fn size(&self, label: u32) -> u32 {
+        self.sizes[label as usize]
+    }
+
+    /// Updates the regions after the Free cell `p` was wrapped, returns whether
+    /// that cut off a part of its region.
+    pub fn remove(&mut self, grid: &Grid, p: Point2D, changes: &mut Vec<RegionChange>) -> bool {
+        let cell = grid.linear_index(p);
+        let label = self.get(cell);
+        if label == NONE {
+            return false;
+        }
+        self.set(cell, NONE);
+        self.sizes[label as usize] -= 1;
+        changes.push(RegionChange::Removed { cell, label });
+
+        let sides = self.ring_groups(grid, p, label);
+        if sides.len() <= 1 {
+            return false;
+        }
+        let parts = self.cut_off_parts(grid, &sides, label);
+        let split = !parts.is_empty();
+        for cells in parts {
+            let new_label = self.sizes.len() as u32;
+            for i in cells.iter() {
+                self.set(*i, new_label);
+            }
+            self.sizes[label as usize] -= cells.len() as u32;
+            self.sizes.push(cells.len() as u32);
+            changes.push(RegionChange::Split { from: label, label: new_label, cells });
+        }
+        split
+    }
+
+    pub fn undo(&mut self, changes: &[RegionChange]) {
+        for change in changes.iter().rev() {
+            match change {
+                RegionChange::Removed { cell, label } => {
+                    self.set(*cell, *label);
+                    self.sizes[*label as usize] += 1;
+                }
+                RegionChange::Split { from, label, cells } => {
+                    assert_eq!(*label as usize, self.sizes.len() - 1);
+                    for i in cells.iter() {
+                        self.set(*i, *from);
+                    }
+                    self.sizes.pop();
+                    self.sizes[*from as usize] += cells.len() as u32;
+                }
+            }
+        }
+    }
+
+    fn get(&self, i: usize) -> u32 {
+        self.labels[i / CHUNK][i % CHUNK]
+    }
+
+    fn set(&mut self, i: usize, label: u32) {
+        Rc::make_mut(&mut self.labels[i / CHUNK])[i % CHUNK] = label;
+    }
+
+    fn has_label(&self, grid: &Grid, p: Point2D, label: u32) -> bool {
+        grid.contains(p) && self.get(grid.linear_index(p)) == label
+    }
+
+    /// One side cell of `p` for every run of region cells around it, runs
+    /// are connected through the corners between them.
+    fn ring_groups(&self, grid: &Grid, p: Point2D, label: u32) -> Vec<Point2D> {
+        let inside = NEIGHBOURS_8.iter().map(|d| self.has_label(grid, p + *d, label)).collect::<Vec<_>>();
+        // start right after a gap so that no run wraps around
+        let start = match inside.iter().position(|i| !i) {
+            Some(i) => i,
+            None => return vec![p + NEIGHBOURS_8[0]],
+        };
+        let mut groups = vec![];
+        let mut represented = false;
+        for k in 1..=8 {
+            let i = (start + k) % 8;
+            if !inside[i] {
+                represented = false;
+            } else if i % 2 == 0 && !represented {
+                groups.push(p + NEIGHBOURS_8[i]);
+                represented = true;
+            }
+        }
+        groups
+    }
+
+    /// Runs searches from `sides` in lockstep for up to `MAX_CUT_OFF` steps
+    /// and returns the cells of every part found to be disconnected from the
+    /// rest, leaving out the last part still growing.
+    fn cut_off_parts(&self, grid: &Grid, sides: &[Point2D], label: u32) -> Vec<Vec<usize>> {
+        let n = sides.len();
+        let mut owner: HashMap<usize, usize> = HashMap::new();
+        let mut queues = sides.iter().map(|p| VecDeque::from(vec![grid.linear_index(*p)])).collect::<Vec<_>>();
+        let mut cells = sides.iter().map(|p| vec![grid.linear_index(*p)]).collect::<Vec<_>>();
+        for (i, p) in sides.iter().enumerate() {
+            owner.insert(grid.linear_index(*p), i);
+        }
+        // union-find over the searches that met
+        let mut group = (0..n).collect::<Vec<_>>();
+        fn find(group: &mut [usize], mut i: usize) -> usize {
+            while group[i] != i {
+                group[i] = group[group[i]];
+                i = group[i];
+            }
+            i
+        }
+
+        let mut parts = vec![];
+        let mut done = vec![false; n];
+        // a part of k cells runs out of them after k steps
+        for _ in 0..=MAX_CUT_OFF {
+            let roots = (0..n).map(|i| find(&mut group, i)).collect::<Vec<_>>();
+            let mut active = roots.clone();
+            active.sort();
+            active.dedup();
+            active.retain(|r| !done[*r]);
+            if active.len() <= 1 {
+                break;
+            }
+            // a part is cut off once all of its searches ran out of cells
+            for r in active.iter() {
+                if (0..n).filter(|i| roots[*i] == *r).all(|i| queues[i].is_empty()) {
+                    done[*r] = true;
+                    parts.push((0..n).filter(|i| roots[*i] == *r).flat_map(|i| cells[i].clone()).collect());
+                }
+            }
+            for i in 0..n {
+                if done[roots[i]] {
+                    continue;
+                }
+                let c = match queues[i].pop_front() {
+                    Some(c) => c,
+                    None => continue,
+                };
+                for d in NEIGHBOURS_4.iter() {
+                    let next = grid.point(c) + *d;
+                    if !self.has_label(grid, next, label) {
+                        continue;
+                    }
+                    let j = grid.linear_index(next);
+                    match owner.get(&j) {
+                        Some(o) => {
+                            let (a, b) = (find(&mut group, *o), find(&mut group, i));
+                            group[a] = b;
+                        }
+                        None => {
+                            owner.insert(j, i);
+                            queues[i].push_back(j);
+                            cells[i].push(j);
+                        }
+                    }
+                }
+            }
+        }
+        parts
+    }
+
+    /// Relabels the cells labelled `from` connected to `start`, returns their number.
+    fn flood(&mut self, grid: &Grid, start: Point2D, from: u32, to: u32) -> u32 {
+        let mut stack = vec![start];
+        self.set(grid.linear_index(start), to);
+        let mut size = 0;
+        while let Some(p) = stack.pop() {
+            size += 1;
+            for q in grid.neighbours_4(p) {
+                let i = grid.linear_index(q);
+                if grid[q] == GridCell::Free && self.get(i) == from {
+                    self.set(i, to);
+                    stack.push(q);
+                }
+            }
+        }
+        size
+    }
+}
+
+#[test]
+fn test_regions() {
+    // brute force labelling: sizes of all the Free components
+    fn components(grid: &Grid) -> Vec<u32> {
+        let fresh = Regions::new(grid);
+        let mut result = fresh.labels().map(|l| fresh.size(l)).collect::<Vec<_>>();
+        result.sort();
+        result
+    }
+
+    let mut grid = Grid::new(9, 7, GridCell::Free);
+    for y in 0..7 {
+        grid.set(Point2D::new(4, y), GridCell::Obstacle);
+    }
+    grid.set(Point2D::new(4, 3), GridCell::Free);
+    let mut regions = Regions::new(&grid);
+    assert_eq!(regions.count(), 1);
+
+    // closing the door splits the map in two
+    let mut changes = vec![];
+    grid.set(Point2D::new(2, 3), GridCell::Wrapped);
+    assert!(!regions.remove(&grid, Point2D::new(2, 3), &mut changes));
+    grid.set(Point2D::new(4, 3), GridCell::Wrapped);
+    let clone = regions.clone();
+    assert!(regions.remove(&grid, Point2D::new(4, 3), &mut changes));
+    assert_eq!(regions.count(), 2);
+    assert_eq!(clone.count(), 1);
+    let left = regions.label(&grid, Point2D::new(0, 0)).unwrap();
+    let right = regions.label(&grid, Point2D::new(8, 0)).unwrap();
+    assert_ne!(left, right);
+    assert_eq!((regions.size(left), regions.size(right)), (27, 28));
+    assert_eq!(regions.size(left) + regions.size(right), grid.num_free);
+    assert_eq!(regions.label(&grid, Point2D::new(4, 3)), None);
+
+    regions.undo(&changes);
+    grid.set(Point2D::new(2, 3), GridCell::Free);
+    grid.set(Point2D::new(4, 3), GridCell::Free);
+    assert_eq!(regions.count(), 1);
+    assert_eq!(regions.size(0), 57);
+
+    // wrap cells in a scrambled order and compare with labelling from scratch
+    let mut changes = vec![];
+    let mut seed = 7u32;
+    for _ in 0..40 {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        let p = Point2D::new((seed >> 16) as i32 % 9, (seed >> 8) as i32 % 7);
+        if grid[p] == GridCell::Free {
+            grid.set(p, GridCell::Wrapped);
+            regions.remove(&grid, p, &mut changes);
+            let mut actual = regions.labels().map(|l| regions.size(l)).collect::<Vec<_>>();
+            actual.sort();
+            assert_eq!(actual, components(&grid));
+        }
+    }
+    regions.undo(&changes);
+    assert_eq!(regions.count(), 1);
+    assert_eq!(regions.size(0), 57);
+
+    // cutting a corridor in the middle of a long loop gives up without splitting
+    let mut grid = Grid::new(MAX_CUT_OFF as u32 + 2, 3, GridCell::Free);
+    for x in 1..MAX_CUT_OFF as i32 + 1 {
+        grid.set(Point2D::new(x, 1), GridCell::Obstacle);
+    }
+    let mut regions = Regions::new(&grid);
+    let mut changes = vec![];
+    grid.set(Point2D::new(0, 1), GridCell::Wrapped);
+    assert!(!regions.remove(&grid, Point2D::new(0, 1), &mut changes));
+    // the other end of the loop really cuts it in two, both parts larger than MAX_CUT_OFF
+    let end = Point2D::new(MAX_CUT_OFF as i32 + 1, 1);
+    grid.set(end, GridCell::Wrapped);
+    assert!(!regions.remove(&grid, end, &mut changes));
+    assert_eq!(regions.count(), 1);
+    assert_eq!(components(&grid).len(), 2);
+}
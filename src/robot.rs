@@ -1,3 +1,5 @@
+use std::cmp;
+
 use crate::geometry::Point2D;
 use crate::grid::Grid;
 
@@ -15,6 +17,8 @@ pub enum Orientation {
     Down,
 }
 
+static ORIENTATIONS: [Orientation; 4] = [Orientation::Left, Orientation::Up, Orientation::Right, Orientation::Down];
+
 impl Orientation {
     pub fn rotate(&self, direction: RotationDirection) -> Orientation {
         match direction {
@@ -39,8 +43,9 @@ impl Orientation {
     }
 
     pub fn unapply_to(&self, p: Point2D) -> Point2D {
+        // the inverse of a rotation is its transpose
         let (ax, ay) = self.multiplier();
-        Point2D::new(p * -ax, p * -ay)
+        Point2D::new(p * Point2D::new(ax.x, ay.x), p * Point2D::new(ax.y, ay.y))
     }
 
     fn multiplier(&self) -> (Point2D, Point2D) {
@@ -65,71 +70,168 @@ impl Orientation {
     }
 }
 
+#[test]
+fn test_orientation() {
+    let p = Point2D::new(2, -1);
+    for o in ORIENTATIONS.iter() {
+        assert_eq!(o.unapply_to(o.apply_to(p)), p);
+    }
+    assert_eq!(Orientation::Right.apply_to(p), p);
+    assert_eq!(Orientation::Up.apply_to(Point2D::new(1, 0)), Point2D::new(0, 1));
+}
+
+/// Cells (relative to the robot, in the robot frame) that the segment from the
+/// centre of the robot cell to the centre of the tentacle cell passes through.
+/// The tentacle cell itself is the last one.
+fn sight_line(t: Point2D) -> Vec<Point2D> {
+    // doubled coordinates keep cell centres integral: the segment goes from
+    // (1, 1) to (2 * t.x + 1, 2 * t.y + 1) and cell (x, y) spans [2x, 2x + 2]
+    let dx = (2 * t.x) as f64;
+    let dy = (2 * t.y) as f64;
+    // the range of the segment parameter for which the coordinate is strictly inside [lo, lo + 2]
+    let range = |lo: i32, d: f64| -> (f64, f64) {
+        if d == 0.0 {
+            if lo < 1 && 1 < lo + 2 { (f64::NEG_INFINITY, f64::INFINITY) } else { (0.0, 0.0) }
+        } else {
+            let a = (lo - 1) as f64 / d;
+            let b = (lo + 1) as f64 / d;
+            if a < b { (a, b) } else { (b, a) }
+        }
+    };
+
+    let mut result = Vec::new();
+    for x in cmp::min(0, t.x)..=cmp::max(0, t.x) {
+        for y in cmp::min(0, t.y)..=cmp::max(0, t.y) {
+            let p = Point2D::new(x, y);
+            if p == Point2D::new(0, 0) || p == t {
+                continue;
+            }
+            let (x_from, x_to) = range(2 * x, dx);
+            let (y_from, y_to) = range(2 * y, dy);
+            let from = x_from.max(y_from);
+            let to = x_to.min(y_to);
+            if from < to && from < 1.0 && to > 0.0 {
+                result.push(p);
+            }
+        }
+    }
+    result.push(t);
+    result
+}
+
+#[test]
+fn test_sight_line() {
+    assert_eq!(sight_line(Point2D::new(1, 0)), vec![Point2D::new(1, 0)]);
+    assert_eq!(sight_line(Point2D::new(1, 1)), vec![Point2D::new(1, 1)]);
+    assert_eq!(sight_line(Point2D::new(3, 0)), vec![Point2D::new(1, 0), Point2D::new(2, 0), Point2D::new(3, 0)]);
+    assert_eq!(
+        sight_line(Point2D::new(2, 1)),
+        vec![Point2D::new(1, 0), Point2D::new(1, 1), Point2D::new(2, 1)]
+    );
+    assert_eq!(
+        sight_line(Point2D::new(-1, -2)),
+        vec![Point2D::new(-1, -1), Point2D::new(0, -1), Point2D::new(-1, -2)]
+    );
+    assert_eq!(sight_line(Point2D::new(2, 2)), vec![Point2D::new(1, 1), Point2D::new(2, 2)]);
+}
+
+#[derive(Clone)]
 pub struct Robot {
     pub id: u8,
     pub position: Point2D,
     pub tentacles: Vec<Point2D>,
     pub orientation: Orientation,
     pub fuel_left: u16,
+    pub drill_left: u16,
+    // sight lines of every tentacle in world offsets, precomputed for each orientation
+    sight_lines: [Vec<Vec<Point2D>>; 4],
 }
 
 impl Robot {
     pub fn new(id: u8, position: Point2D) -> Robot {
-        Robot {
+        let mut robot = Robot {
             id,
             position,
-            tentacles: vec![
-                Point2D::new(1, 1),
-                Point2D::new(1, -1),
-                Point2D::new(1, 0),
-            ],
+            tentacles: vec![],
             orientation: Orientation::Right,
             fuel_left: 0,
+            drill_left: 0,
+            sight_lines: [vec![], vec![], vec![], vec![]],
+        };
+        for t in [Point2D::new(1, 1), Point2D::new(1, -1), Point2D::new(1, 0)].iter() {
+            robot.push_tentacle(*t);
         }
+        robot
     }
 
     pub fn rotate(&mut self, direction: RotationDirection) {
         self.orientation = self.orientation.rotate(direction);
     }
 
+    /// The robot cell and every tentacle cell that is in direct line of sight,
+    /// i.e. the segment between the cell centres crosses no obstacle.
     pub fn get_visible_parts(&self, grid: &Grid) -> Vec<Point2D> {
-        // TODO: this is a quick and dirty visibility check
-        //   4
-        //   3
-        //  021 <- tentacle indices, the algorithm depends on this order
-        //   R
-
         let mut result = Vec::new();
         result.push(self.position);
 
-        for t in self.tentacles.iter().take(2) {
-            let p = self.orientation.apply_to(*t) + self.position;
-            if grid.contains(p) && !grid[p].is_obstacle() {
-                result.push(p);
-            }
-        }
-
-        for t in self.tentacles.iter().skip(2) {
-            let p = self.orientation.apply_to(*t) + self.position;
-            if grid.contains(p) && !grid[p].is_obstacle() {
-                result.push(p);
-            } else {
-                break
+        let is_free = |p: Point2D| grid.contains(p) && !grid[p].is_obstacle();
+        for line in self.sight_lines[self.orientation as usize].iter() {
+            if line.iter().all(|p| is_free(*p + self.position)) {
+                result.push(*line.last().unwrap() + self.position);
             }
         }
         result
     }
 
     pub fn attach_tentacle(&mut self, p: Point2D) {
-        self.tentacles.push(self.orientation.unapply_to(p));
+        self.push_tentacle(self.orientation.unapply_to(p));
     }
 
     pub fn detach_last_tentacle(&mut self) {
         self.tentacles.remove(self.tentacles.len() - 1);
+        for lines in self.sight_lines.iter_mut() {
+            lines.pop();
+        }
     }
 
     pub fn next_attachment_point(&self) -> Point2D {
         let x = self.tentacles.iter().map(|t| t.x).max().unwrap();
         self.orientation.apply_to(Point2D::new(x + 1, 0))
     }
+
+    fn push_tentacle(&mut self, t: Point2D) {
+        self.tentacles.push(t);
+        let line = sight_line(t);
+        for o in ORIENTATIONS.iter() {
+            self.sight_lines[*o as usize].push(line.iter().map(|p| o.apply_to(*p)).collect());
+        }
+    }
+}
+
+#[test]
+fn test_visible_parts() {
+    use crate::grid::GridCell;
+
+    let mut grid = Grid::new(5, 5, GridCell::Free);
+    grid.set(Point2D::new(2, 1), GridCell::Obstacle);
+    let mut robot = Robot::new(0, Point2D::new(1, 1));
+    assert_eq!(
+        robot.get_visible_parts(&grid),
+        vec![Point2D::new(1, 1), Point2D::new(2, 2), Point2D::new(2, 0)]
+    );
+    robot.rotate(RotationDirection::CCW);
+    robot.attach_tentacle(Point2D::new(1, 2));
+    assert_eq!(
+        robot.get_visible_parts(&grid),
+        vec![Point2D::new(1, 1), Point2D::new(0, 2), Point2D::new(2, 2), Point2D::new(1, 2), Point2D::new(2, 3)]
+    );
+    // (2, 3) is only seen through (1, 2) and (2, 2)
+    grid.set(Point2D::new(1, 2), GridCell::Obstacle);
+    assert_eq!(
+        robot.get_visible_parts(&grid),
+        vec![Point2D::new(1, 1), Point2D::new(0, 2), Point2D::new(2, 2)]
+    );
+    robot.detach_last_tentacle();
+    robot.rotate(RotationDirection::CW);
+    assert_eq!(robot.get_visible_parts(&grid), vec![Point2D::new(1, 1), Point2D::new(2, 2), Point2D::new(2, 0)]);
 }
@@ -0,0 +1,157 @@
+use std::fmt;
+
+use crate::geometry::Point2D;
+use crate::state::Action;
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at byte {}: {}", self.offset, self.message)
+    }
+}
+
+pub struct Solution;
+
+impl Solution {
+    /// Parses a `.sol` file into per-robot action lists, robot 0 first.
+    pub fn parse(s: &str) -> Result<Vec<Vec<Action>>, ParseError> {
+        let mut parser = Parser::new(s.trim_end());
+        let mut result = vec![vec![]];
+        while let Some(c) = parser.next_byte() {
+            let action = match c {
+                b'W' => Action::MoveUp,
+                b'S' => Action::MoveDown,
+                b'A' => Action::MoveLeft,
+                b'D' => Action::MoveRight,
+                b'Q' => Action::TurnCCW,
+                b'E' => Action::TurnCW,
+                b'Z' => Action::NoOp,
+                b'F' => Action::Accelerate,
+                b'L' => Action::Drill,
+                b'R' => Action::InstallBeacon,
+                b'C' => Action::Clone,
+                b'B' => Action::Attach(parser.point()?),
+                b'T' => Action::Teleport(parser.point()?),
+                b'#' => {
+                    result.push(vec![]);
+                    continue
+                }
+                _ => return Err(parser.error_at(parser.offset - 1, format!("unexpected character '{}'", c as char))),
+            };
+            result.last_mut().unwrap().push(action);
+        }
+        Ok(result)
+    }
+
+    /// Moves the teleport targets of a solution by `offset`, e.g. from grid to
+    /// map coordinates with `State::origin`. Attach coordinates are relative to
+    /// the robot and stay as they are.
+    pub fn translate(solution: &mut [Vec<Action>], offset: Point2D) {
+        for action in solution.iter_mut().flat_map(|actions| actions.iter_mut()) {
+            if let Action::Teleport(p) = action {
+                *p = *p + offset;
+            }
+        }
+    }
+
+    pub fn write(solution: &[Vec<Action>]) -> String {
+        solution.iter()
+            .map(|actions| actions.iter().map(|a| a.to_string()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("#")
+    }
+}
+
+/// Reads the points and actions of the contest formats byte by byte.
+pub struct Parser<'a> {
+    data: &'a [u8],
+    pub offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(s: &'a str) -> Parser<'a> {
+        Parser { data: s.as_bytes(), offset: 0 }
+    }
+
+    pub fn peek(&self) -> Option<u8> {
+        self.data.get(self.offset).cloned()
+    }
+
+    pub fn next_byte(&mut self) -> Option<u8> {
+        let c = self.data.get(self.offset).cloned();
+        if c.is_some() {
+            self.offset += 1;
+        }
+        c
+    }
+
+    pub fn expect(&mut self, expected: u8) -> Result<(), ParseError> {
+        match self.next_byte() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error_at(self.offset.saturating_sub(1), format!("expected '{}'", expected as char))),
+        }
+    }
+
+    pub fn number(&mut self) -> Result<i32, ParseError> {
+        let start = self.offset;
+        if self.data.get(self.offset) == Some(&b'-') {
+            self.offset += 1;
+        }
+        while self.data.get(self.offset).is_some_and(|c| c.is_ascii_digit()) {
+            self.offset += 1;
+        }
+        std::str::from_utf8(&self.data[start..self.offset]).unwrap()
+            .parse::<i32>()
+            .map_err(|_| self.error_at(start, "expected a number".to_string()))
+    }
+
+    pub fn point(&mut self) -> Result<Point2D, ParseError> {
+        self.expect(b'(')?;
+        let x = self.number()?;
+        self.expect(b',')?;
+        let y = self.number()?;
+        self.expect(b')')?;
+        Ok(Point2D::new(x, y))
+    }
+
+    pub fn error_at(&self, offset: usize, message: String) -> ParseError {
+        ParseError { offset, message }
+    }
+}
+
+#[test]
+fn test_solution_parsing() {
+    use crate::state::Action::*;
+    assert_eq!(Solution::parse("WSAD\n"), Ok(vec![vec![MoveUp, MoveDown, MoveLeft, MoveRight]]));
+    assert_eq!(Solution::parse(""), Ok(vec![vec![]]));
+    assert_eq!(
+        Solution::parse("QEZFLRCB(1,-2)#T(10,3)#"),
+        Ok(vec![
+            vec![TurnCCW, TurnCW, NoOp, Accelerate, Drill, InstallBeacon, Clone, Attach(Point2D::new(1, -2))],
+            vec![Teleport(Point2D::new(10, 3))],
+            vec![],
+        ])
+    );
+    assert_eq!(Solution::parse("WX").unwrap_err().offset, 1);
+    assert_eq!(Solution::parse("WB(1,").unwrap_err().offset, 5);
+    assert_eq!(Solution::parse("B(1;2)").unwrap_err().offset, 3);
+    assert_eq!(Solution::parse("T(,2)").unwrap_err().offset, 2);
+
+    let s = "DDB(1,2)#WZT(-1,0)";
+    assert_eq!(Solution::write(&Solution::parse(s).unwrap()), s);
+    let mut solution = Solution::parse(s).unwrap();
+    Solution::translate(&mut solution, Point2D::new(3, -2));
+    assert_eq!(Solution::write(&solution), "DDB(1,2)#WZT(2,-2)");
+    for data in &[
+        include_str!("../part-1-initial/prob-030.sol"),
+        include_str!("../part-2-teleports/prob-160.sol"),
+        include_str!("../part-3-clones/prob-230.sol"),
+    ] {
+        assert_eq!(Solution::write(&Solution::parse(data).unwrap()), data.trim_end());
+    }
+}
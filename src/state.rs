@@ -1,11 +1,17 @@
 use std::collections::HashMap;
+use std::fmt;
 
 use crate::core;
 use crate::geometry::Point2D;
 use crate::grid::{Grid, GridCell};
+use crate::inventory::Inventory;
+use crate::regions::{RegionChange, Regions};
 use crate::robot::{Robot, RotationDirection};
 
 static FUEL_INITIAL_VALUE: u16 = 50;
+static DRILL_INITIAL_VALUE: u16 = 30;
+// beacons closer than this (manhattan distance) to an installed one are rejected
+static BEACON_MIN_DISTANCE: i32 = 1;
 
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
 pub enum BoosterType {
@@ -23,6 +29,37 @@ pub struct Booster {
     pub position: Point2D,
 }
 
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ActionError {
+    OutOfBounds,
+    HitObstacle,
+    NoBoosterInInventory(BoosterType),
+    AttachNotAdjacent,
+    AttachOccupied,
+    NotOnSpawnPoint,
+    BeaconOnSpawnPoint,
+    BeaconTooClose,
+    UnknownBeacon,
+    UnknownRobot,
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ActionError::OutOfBounds => write!(f, "moving outside of the map"),
+            ActionError::HitObstacle => write!(f, "moving into an obstacle"),
+            ActionError::NoBoosterInInventory(type_) => write!(f, "no {:?} booster available", type_),
+            ActionError::AttachNotAdjacent => write!(f, "the new manipulator is not adjacent to an existing one"),
+            ActionError::AttachOccupied => write!(f, "a manipulator is already attached there"),
+            ActionError::NotOnSpawnPoint => write!(f, "cloning outside of a spawn point"),
+            ActionError::BeaconOnSpawnPoint => write!(f, "installing a beacon on a spawn point"),
+            ActionError::BeaconTooClose => write!(f, "installing a beacon too close to another one"),
+            ActionError::UnknownBeacon => write!(f, "teleporting to a location without a beacon"),
+            ActionError::UnknownRobot => write!(f, "acting with a robot that doesn't exist"),
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum Action {
     MoveLeft,
@@ -51,6 +88,18 @@ impl Action {
         }
     }
 
+    /// The booster used up by the action.
+    pub fn booster(&self) -> Option<BoosterType> {
+        match self {
+            Action::Attach(_) => Some(BoosterType::B),
+            Action::Clone => Some(BoosterType::C),
+            Action::Accelerate => Some(BoosterType::F),
+            Action::Drill => Some(BoosterType::L),
+            Action::InstallBeacon => Some(BoosterType::R),
+            _ => None,
+        }
+    }
+
     pub fn rotation_direction(&self) -> Option<RotationDirection> {
         match self {
             Action::TurnCW => Some(RotationDirection::CW),
@@ -60,33 +109,97 @@ impl Action {
     }
 }
 
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::MoveLeft => write!(f, "A"),
+            Action::MoveUp => write!(f, "W"),
+            Action::MoveRight => write!(f, "D"),
+            Action::MoveDown => write!(f, "S"),
+            Action::TurnCW => write!(f, "E"),
+            Action::TurnCCW => write!(f, "Q"),
+            Action::Attach(p) => write!(f, "B({},{})", p.x, p.y),
+            Action::Clone => write!(f, "C"),
+            Action::Accelerate => write!(f, "F"),
+            Action::Drill => write!(f, "L"),
+            Action::NoOp => write!(f, "Z"),
+            Action::InstallBeacon => write!(f, "R"),
+            Action::Teleport(p) => write!(f, "T({},{})", p.x, p.y),
+        }
+    }
+}
+
 pub struct ReverseAction {
     pub action: Action,
-    pub picked_up_booster: Option<BoosterType>,
-    pub picked_up_booster_position: Point2D, // TODO: merge with above
+    pub picked_up_boosters: Vec<Booster>,
+    // picked up on the first cell of a fast-wheels double move, usable right away
+    pub usable_boosters: Vec<Booster>,
     pub wrapped_points: HashMap<Point2D, GridCell>,
     pub robot_fuel_left: u16,
+    pub robot_drill_left: u16,
+    pub drilled_points: Vec<Point2D>,
     pub made_two_moves: bool,
     pub teleported_from: Point2D,
+    pub region_changes: Vec<RegionChange>,
+    // the cells this action wrapped cut off a part of their region, see `Regions`
+    pub split_region: bool,
+}
+
+impl ReverseAction {
+    fn new(action: Action) -> ReverseAction {
+        ReverseAction {
+            action,
+            picked_up_boosters: vec![],
+            usable_boosters: vec![],
+            wrapped_points: HashMap::new(),
+            robot_fuel_left: 0,
+            robot_drill_left: 0,
+            drilled_points: vec![],
+            made_two_moves: false,
+            teleported_from: Point2D::new(0, 0),
+            region_changes: vec![],
+            split_region: false,
+        }
+    }
+}
+
+pub struct TickReverse {
+    // `None` for the robots that didn't act
+    pub reverse_actions: Vec<Option<ReverseAction>>,
+    pub promoted_boosters: Vec<BoosterType>,
 }
 
+#[derive(Clone)]
 pub struct State {
     grid: Grid,
+    regions: Regions,
     boosters: HashMap<Point2D, BoosterType>,
     robots: Vec<Robot>,
     beacons: Vec<Point2D>,
-    collected_boosters: HashMap<BoosterType, u8>,
+    inventory: Inventory,
+    time: u32,
+    origin: Point2D,
 }
 
 impl State {
-    pub fn new(grid: Grid, boosters: Vec<Booster>, initial_position: Point2D) -> State {
-        State {
+    pub fn new(grid: Grid, boosters: Vec<Booster>, initial_position: Point2D, inventory: Inventory) -> State {
+        let mut state = State {
+            regions: Regions::new(&grid),
             grid,
             boosters: boosters.into_iter().map(|b| (b.position, b.type_)).collect(),
             robots: vec![Robot::new(0, initial_position)],
             beacons: vec![],
-            collected_boosters: HashMap::new(),
+            inventory,
+            time: 0,
+            origin: Point2D::new(0, 0),
+        };
+        // the initial position is wrapped and its booster collected before the first action
+        state.wrap(&mut ReverseAction::new(Action::NoOp));
+        if let Some(type_) = state.boosters.get(&initial_position).cloned().filter(|t| *t != BoosterType::X) {
+            state.boosters.remove(&initial_position);
+            state.inventory.collect(type_);
         }
+        state
     }
 
     pub fn robot(&self, id: u32) -> &Robot {
@@ -97,8 +210,37 @@ impl State {
         &mut self.robots[id as usize]
     }
 
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    /// Where the bottom left corner of the grid is in the coordinates of the map file.
+    pub fn origin(&self) -> Point2D {
+        self.origin
+    }
+
+    pub fn set_origin(&mut self, origin: Point2D) {
+        self.origin = origin;
+    }
+
+    /// Positions of the boosters of a type still lying on the map.
+    pub fn booster_positions(&self, type_: BoosterType) -> Vec<Point2D> {
+        self.boosters.iter()
+            .filter(|(_, t)| **t == type_)
+            .map(|(p, _)| *p)
+            .collect()
+    }
+
+    pub fn regions(&self) -> &Regions {
+        &self.regions
+    }
+
+    pub fn inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+
     pub fn max_points(&self) -> u32 {
-        (1000.0 * ((self.grid.width * self.grid.height) as f64).log2()).ceil() as u32
+        (1000.0 * (self.grid.area() as f64).log2()).ceil() as u32
     }
 
     pub fn has_wrappable_cells(&self) -> bool {
@@ -106,21 +248,53 @@ impl State {
     }
 
     fn maybe_collect_booster(&mut self, position: Point2D, reverse_action: &mut ReverseAction) {
-        match self.boosters.remove(&position) {
-            Some(type_) => {
-                if type_ == BoosterType::X {
-                    // put spawning point back
-                    self.boosters.insert(position, type_);
-                } else {
-                    reverse_action.picked_up_booster = Some(type_);
-                    reverse_action.picked_up_booster_position = position;
-                    self.collected_boosters.insert(type_, self.collected_boosters[&type_] + 1);
-                }
+        if let Some(type_) = self.boosters.remove(&position) {
+            if type_ == BoosterType::X {
+                // put spawning point back
+                self.boosters.insert(position, type_);
+            } else {
+                reverse_action.picked_up_boosters.push(Booster { type_, position });
+                self.inventory.pick_up(type_);
             }
-            None => {}
         }
     }
 
+    /// Finishes the current tick, making the boosters picked up during it usable.
+    fn end_tick(&mut self) -> Vec<BoosterType> {
+        let promoted = self.inventory.promote();
+        self.time += 1;
+        promoted
+    }
+
+    fn check_move(&self, robot: &Robot, p: Point2D) -> Result<(), ActionError> {
+        if !self.grid.contains(p) {
+            return Err(ActionError::OutOfBounds);
+        }
+        match self.grid[p] {
+            GridCell::Obstacle if robot.drill_left == 0 => Err(ActionError::HitObstacle),
+            GridCell::Void => Err(ActionError::OutOfBounds),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_booster(&self, type_: BoosterType) -> Result<(), ActionError> {
+        if self.inventory.has(type_) {
+            Ok(())
+        } else {
+            Err(ActionError::NoBoosterInInventory(type_))
+        }
+    }
+
+    fn move_robot(&mut self, id: u32, p: Point2D, reverse_action: &mut ReverseAction) {
+        if self.grid[p] == GridCell::Obstacle {
+            assert!(self.robot(id).drill_left > 0);
+            self.grid.set(p, GridCell::Free);
+            reverse_action.drilled_points.push(p);
+        }
+        self.robot_mut(id).position = p;
+        self.wrap(reverse_action);
+    }
+
     fn wrap(&mut self, reverse_action: &mut ReverseAction) {
         for robot in self.robots.iter() {
             assert!(!self.grid[robot.position].is_obstacle());
@@ -128,21 +302,29 @@ impl State {
                 if self.grid[part] == GridCell::Free {
                     reverse_action.wrapped_points.insert(part, self.grid[part]);
                     self.grid.set(part, GridCell::Wrapped);
+                    if self.regions.remove(&self.grid, part, &mut reverse_action.region_changes) {
+                        reverse_action.split_region = true;
+                    }
                 }
             }
         }
     }
 
     fn unwrap(&mut self, reverse_action: &ReverseAction) {
+        self.regions.undo(&reverse_action.region_changes);
         for (p, cell) in reverse_action.wrapped_points.iter() {
             self.grid.set(*p, *cell);
         }
+        for p in reverse_action.drilled_points.iter() {
+            self.grid.set(*p, GridCell::Obstacle);
+        }
     }
 }
 
 impl core::State for State {
     type Action = Action;
     type ReverseAction = ReverseAction;
+    type ActionError = ActionError;
 
     fn info(&self) -> String {
         format!("dim: {}x{}, max points: {}", self.grid.width, self.grid.height, self.max_points())
@@ -156,59 +338,78 @@ impl core::State for State {
         self.robots.len() as u32
     }
 
-    fn can_apply(&self, id: u32, action: Action) -> bool {
+    fn can_apply(&self, id: u32, action: Action) -> Result<(), ActionError> {
         use crate::state::Action::*;
-        let robot = &self.robots[id as usize];
+        let robot = self.robots.get(id as usize).ok_or(ActionError::UnknownRobot)?;
         match action {
             MoveLeft | MoveUp | MoveRight | MoveDown => {
-                let new_position = robot.position + action.move_point().unwrap();
-                self.grid.contains(new_position) && !self.grid[new_position].is_obstacle()
+                self.check_move(robot, robot.position + action.move_point().unwrap())
             }
-            TurnCW | TurnCCW => true,
+            TurnCW | TurnCCW => Ok(()),
             Attach(location) => {
-                self.collected_boosters[&BoosterType::B] > 0
-                        && robot.tentacles.iter()
-                            .map(|p| robot.orientation.apply_to(*p))
-                            .map(|p| p.manhattan_dist(location))
-                            .min() == Some(1)
+                self.check_booster(BoosterType::B)?;
+                let tentacles = robot.tentacles.iter()
+                    .map(|p| robot.orientation.apply_to(*p))
+                    .collect::<Vec<_>>();
+                if location == Point2D::new(0, 0) || tentacles.contains(&location) {
+                    Err(ActionError::AttachOccupied)
+                } else if tentacles.iter().all(|p| p.manhattan_dist(location) != 1) {
+                    Err(ActionError::AttachNotAdjacent)
+                } else {
+                    Ok(())
+                }
             }
-            Accelerate => self.collected_boosters[&BoosterType::F] > 0,
+            Accelerate => self.check_booster(BoosterType::F),
+            Drill => self.check_booster(BoosterType::L),
             Clone => {
-                self.collected_boosters[&BoosterType::C] > 0
-                        && self.boosters.get(&robot.position) == Some(&BoosterType::X)
+                self.check_booster(BoosterType::C)?;
+                if self.boosters.get(&robot.position) != Some(&BoosterType::X) {
+                    return Err(ActionError::NotOnSpawnPoint);
+                }
+                Ok(())
             }
-            NoOp => true,
-            _ => unimplemented!("Not supported yet")
+            InstallBeacon => {
+                self.check_booster(BoosterType::R)?;
+                if self.boosters.get(&robot.position) == Some(&BoosterType::X) {
+                    return Err(ActionError::BeaconOnSpawnPoint);
+                }
+                if self.beacons.iter().any(|b| b.manhattan_dist(robot.position) < BEACON_MIN_DISTANCE) {
+                    return Err(ActionError::BeaconTooClose);
+                }
+                Ok(())
+            }
+            Teleport(location) => {
+                if !self.beacons.contains(&location) {
+                    return Err(ActionError::UnknownBeacon);
+                }
+                Ok(())
+            }
+            NoOp => Ok(()),
         }
     }
 
-    fn apply(&mut self, id: u32, action: Action) -> ReverseAction {
-        let mut reverse_action = ReverseAction {
-            action,
-            picked_up_booster: None,
-            picked_up_booster_position: Point2D::new(0, 0),
-            wrapped_points: HashMap::new(),
-            robot_fuel_left: 0,
-            made_two_moves: false,
-            teleported_from: Point2D::new(0, 0),
-        };
-        self.maybe_collect_booster(self.robot(id).position, &mut reverse_action);
+    fn apply(&mut self, id: u32, action: Action) -> Result<ReverseAction, ActionError> {
+        self.can_apply(id, action)?;
+        let mut reverse_action = ReverseAction::new(action);
         reverse_action.robot_fuel_left = self.robot(id).fuel_left;
+        reverse_action.robot_drill_left = self.robot(id).drill_left;
 
         use crate::state::Action::*;
         match action {
             MoveLeft | MoveUp | MoveRight | MoveDown => {
-                self.robot_mut(id).position = self.robot(id).position + action.move_point().unwrap();
-                assert!(!self.grid[self.robot(id).position].is_obstacle());
-                self.wrap(&mut reverse_action);
-                if self.robot(id).fuel_left > 0 {
-                    self.maybe_collect_booster(self.robot(id).position, &mut reverse_action);
-                    let new_position = self.robot(id).position + action.move_point().unwrap();
-                    if self.grid.contains(new_position) && !self.grid[new_position].is_obstacle() {
-                        self.robot_mut(id).position = new_position;
-                        self.wrap(&mut reverse_action);
-                        reverse_action.made_two_moves = true;
+                let new_position = self.robot(id).position + action.move_point().unwrap();
+                self.move_robot(id, new_position, &mut reverse_action);
+                let new_position = self.robot(id).position + action.move_point().unwrap();
+                if self.robot(id).fuel_left > 0 && self.check_move(self.robot(id), new_position).is_ok() {
+                    // a booster passed over during the move can be used by the next robots of the tick
+                    let passed = self.robot(id).position;
+                    if let Some(type_) = self.boosters.get(&passed).cloned().filter(|t| *t != BoosterType::X) {
+                        self.boosters.remove(&passed);
+                        self.inventory.collect(type_);
+                        reverse_action.usable_boosters.push(Booster { type_, position: passed });
                     }
+                    self.move_robot(id, new_position, &mut reverse_action);
+                    reverse_action.made_two_moves = true;
                 }
             }
             TurnCW | TurnCCW => {
@@ -216,64 +417,57 @@ impl core::State for State {
                 self.wrap(&mut reverse_action);
             }
             Attach(location) => {
-                let n = self.collected_boosters[&BoosterType::B];
-                assert!(n > 0);
-                self.collected_boosters.insert(BoosterType::B, n - 1);
-                assert!(
-                    self.robot(id).tentacles.iter()
-                        .map(|p| self.robot(id).orientation.apply_to(*p))
-                        .map(|p| p.manhattan_dist(location))
-                        .min() == Some(1)
-                );
+                self.inventory.take(BoosterType::B);
                 self.robot_mut(id).attach_tentacle(location);
                 self.wrap(&mut reverse_action);
             }
             Accelerate => {
-                let n = self.collected_boosters[&BoosterType::F];
-                assert!(n > 0);
-                self.collected_boosters.insert(BoosterType::F, n - 1);
+                self.inventory.take(BoosterType::F);
                 self.robot_mut(id).fuel_left += FUEL_INITIAL_VALUE;
                 if self.robot(id).fuel_left == FUEL_INITIAL_VALUE {
                     self.robot_mut(id).fuel_left += 1 // accounting for decrement below
                 }
             }
+            Drill => {
+                self.inventory.take(BoosterType::L);
+                self.robot_mut(id).drill_left += DRILL_INITIAL_VALUE;
+                if self.robot(id).drill_left == DRILL_INITIAL_VALUE {
+                    self.robot_mut(id).drill_left += 1 // accounting for decrement below
+                }
+            }
             Clone => {
-                assert!(self.boosters[&self.robot(id).position] == BoosterType::X);
-                let n = self.collected_boosters[&BoosterType::C];
-                assert!(n > 0);
-                self.collected_boosters.insert(BoosterType::C, n - 1);
+                self.inventory.take(BoosterType::C);
                 self.robots.push(Robot::new(self.robots.len() as u8, self.robot(id).position));
                 self.wrap(&mut reverse_action);
             }
             InstallBeacon => {
-                assert!(self.boosters[&self.robot(id).position] != BoosterType::X);
-                let n = self.collected_boosters[&BoosterType::R];
-                assert!(n > 0);
-                self.collected_boosters.insert(BoosterType::R, n - 1);
+                self.inventory.take(BoosterType::R);
                 self.beacons.push(self.robot(id).position);
             }
             Teleport(location) => {
-                assert!(self.beacons.contains(&location));
                 reverse_action.teleported_from = self.robot(id).position;
                 self.robot_mut(id).position = location;
                 self.wrap(&mut reverse_action);
             }
             NoOp => {}
-            _ => unimplemented!("Not supported yet"),
         }
 
-        self.robot_mut(id).fuel_left = std::cmp::max(0, self.robot(id).fuel_left - 1);
+        // boosters are collected after the action so that they only become usable on the next one
+        self.maybe_collect_booster(self.robot(id).position, &mut reverse_action);
+        self.robot_mut(id).fuel_left = self.robot(id).fuel_left.saturating_sub(1);
+        self.robot_mut(id).drill_left = self.robot(id).drill_left.saturating_sub(1);
 
-        reverse_action
+        Ok(reverse_action)
     }
 
     fn unapply(&mut self, id: u32, reverse_action: ReverseAction) -> Action {
-        match reverse_action.picked_up_booster {
-            Some(type_) => {
-                self.boosters.insert(reverse_action.picked_up_booster_position, type_);
-                self.collected_boosters.insert(type_, self.collected_boosters[&type_] - 1);
-            }
-            None => {}
+        for booster in reverse_action.picked_up_boosters.iter().rev() {
+            self.boosters.insert(booster.position, booster.type_);
+            self.inventory.drop_pending(booster.type_);
+        }
+        for booster in reverse_action.usable_boosters.iter() {
+            self.boosters.insert(booster.position, booster.type_);
+            self.inventory.take(booster.type_);
         }
 
         use crate::state::Action::*;
@@ -286,37 +480,271 @@ impl core::State for State {
                 self.unwrap(&reverse_action);
             }
             TurnCW | TurnCCW => {
-                self.robot_mut(id).rotate(reverse_action.action.rotation_direction().unwrap());
+                let direction = match reverse_action.action.rotation_direction().unwrap() {
+                    RotationDirection::CW => RotationDirection::CCW,
+                    RotationDirection::CCW => RotationDirection::CW,
+                };
+                self.robot_mut(id).rotate(direction);
                 self.unwrap(&reverse_action);
             }
             Attach(_) => {
-                self.collected_boosters.insert(BoosterType::B, self.collected_boosters[&BoosterType::B] + 1);
+                self.inventory.restore(BoosterType::B);
                 self.robot_mut(id).detach_last_tentacle();
                 self.unwrap(&reverse_action);
             }
             Accelerate => {
-                self.collected_boosters.insert(BoosterType::F, self.collected_boosters[&BoosterType::F] + 1);
+                self.inventory.restore(BoosterType::F);
+            }
+            Drill => {
+                self.inventory.restore(BoosterType::L);
             }
             Clone => {
-                self.collected_boosters.insert(BoosterType::C, self.collected_boosters[&BoosterType::C] + 1);
+                self.inventory.restore(BoosterType::C);
                 self.robots.remove(self.robots.len() - 1);
                 self.unwrap(&reverse_action);
             }
             InstallBeacon => {
-                self.collected_boosters.insert(BoosterType::R, self.collected_boosters[&BoosterType::R] + 1);
+                self.inventory.restore(BoosterType::R);
                 let position = self.robot(id).position;
-                self.beacons.remove_item(&position);
+                self.beacons.retain(|b| *b != position);
             }
             Teleport(_) => {
                 self.robot_mut(id).position = reverse_action.teleported_from;
                 self.unwrap(&reverse_action);
             }
             NoOp => {}
-            _ => unimplemented!("Not supported yet"),
         }
-        
+
         self.robot_mut(id).fuel_left = reverse_action.robot_fuel_left;
+        self.robot_mut(id).drill_left = reverse_action.robot_drill_left;
         
         reverse_action.action
     }
+
+    type TickReverse = TickReverse;
+
+    fn tick(&self) -> u32 {
+        self.time
+    }
+
+    fn apply_tick(&mut self, actions: &[Option<Action>]) -> Result<TickReverse, (u32, ActionError)> {
+        if let Some(id) = (self.robots.len()..actions.len()).find(|id| actions[*id].is_some()) {
+            return Err((id as u32, ActionError::UnknownRobot));
+        }
+        let robots = self.robots.len();
+        let mut reverse_actions = Vec::with_capacity(robots);
+        for id in 0..robots {
+            let action = match actions.get(id).cloned().flatten() {
+                Some(action) => action,
+                None => {
+                    reverse_actions.push(None);
+                    continue;
+                }
+            };
+            match self.apply(id as u32, action) {
+                Ok(reverse_action) => reverse_actions.push(Some(reverse_action)),
+                Err(error) => {
+                    // roll back the robots that already acted
+                    while let Some(reverse_action) = reverse_actions.pop() {
+                        if let Some(reverse_action) = reverse_action {
+                            self.unapply(reverse_actions.len() as u32, reverse_action);
+                        }
+                    }
+                    return Err((id as u32, error));
+                }
+            }
+        }
+        Ok(TickReverse {
+            reverse_actions,
+            promoted_boosters: self.end_tick(),
+        })
+    }
+
+    fn unapply_tick(&mut self, reverse: TickReverse) -> Vec<Option<Action>> {
+        self.time -= 1;
+        self.inventory.demote(reverse.promoted_boosters);
+        let mut actions = reverse.reverse_actions.into_iter().enumerate().rev()
+            .map(|(id, reverse_action)| reverse_action.map(|r| self.unapply(id as u32, r)))
+            .collect::<Vec<_>>();
+        actions.reverse();
+        actions
+    }
+}
+
+#[test]
+fn test_drill() {
+    use crate::core::State as _;
+    use crate::problem::Problem;
+
+    let mut state = Problem::parse("(0,0),(5,0),(5,3),(0,3)#(0,1)#(2,0),(3,0),(3,3),(2,3)#L(1,1)").unwrap();
+    let obstacle = Point2D::new(2, 1);
+    state.apply_tick(&[Some(Action::MoveRight)]).unwrap();
+    assert_eq!(state.can_apply(0, Action::MoveRight), Err(ActionError::HitObstacle));
+    assert_eq!(state.can_apply(0, Action::Drill), Ok(()));
+    state.apply_tick(&[Some(Action::Drill)]).unwrap();
+    assert_eq!(state.can_apply(0, Action::Drill), Err(ActionError::NoBoosterInInventory(BoosterType::L)));
+    assert_eq!(state.robot(0).drill_left, DRILL_INITIAL_VALUE);
+    let reverse = state.apply_tick(&[Some(Action::MoveRight)]).unwrap();
+    assert_eq!(state.grid[obstacle], GridCell::Wrapped);
+    assert_eq!(reverse.reverse_actions[0].as_ref().unwrap().drilled_points, vec![obstacle]);
+    state.unapply_tick(reverse);
+    assert_eq!(state.grid[obstacle], GridCell::Obstacle);
+    assert_eq!(state.robot(0).position, Point2D::new(1, 1));
+    assert_eq!(state.robot(0).drill_left, DRILL_INITIAL_VALUE);
+
+    for _ in 0..3 {
+        state.apply_tick(&[Some(Action::MoveRight)]).unwrap();
+    }
+    assert_eq!(state.robot(0).position, Point2D::new(4, 1));
+    // never leaves the map
+    assert_eq!(state.can_apply(0, Action::MoveRight), Err(ActionError::OutOfBounds));
+    for _ in 0..DRILL_INITIAL_VALUE - 3 {
+        state.apply_tick(&[Some(Action::NoOp)]).unwrap();
+    }
+    assert_eq!(state.robot(0).drill_left, 0);
+}
+
+#[test]
+fn test_ticks() {
+    use crate::core::State as _;
+    use crate::problem::Problem;
+
+    let mut state = Problem::parse("(0,0),(4,0),(4,3),(0,3)#(0,0)##F(1,0);C(2,0);X(0,0);B(0,1)").unwrap();
+    state.apply(0, Action::MoveRight).unwrap();
+    // a booster picked up during a tick is pending until the tick ends
+    assert_eq!(state.can_apply(0, Action::Accelerate), Err(ActionError::NoBoosterInInventory(BoosterType::F)));
+    assert_eq!(state.end_tick(), vec![BoosterType::F]);
+    assert_eq!(state.can_apply(0, Action::Accelerate), Ok(()));
+    for action in [Action::MoveRight, Action::MoveLeft, Action::MoveLeft].iter() {
+        state.apply_tick(&[Some(*action)]).unwrap();
+    }
+    assert_eq!(state.tick(), 4);
+    let clone = state.apply_tick(&[Some(Action::Clone)]).unwrap();
+    assert_eq!(state.agents_count(), 2);
+    assert_eq!(state.robot(1).position, Point2D::new(0, 0));
+
+    // the clone acts from the next tick, the pickup of robot 0 is pending for robot 1
+    let reverse = state.apply_tick(&[Some(Action::MoveUp), Some(Action::TurnCW)]).unwrap();
+    assert_eq!(reverse.promoted_boosters, vec![BoosterType::B]);
+    assert_eq!(state.robot(1).orientation, crate::robot::Orientation::Down);
+    let wrapped = state.grid.num_wrapped;
+    assert_eq!(state.unapply_tick(reverse), vec![Some(Action::MoveUp), Some(Action::TurnCW)]);
+    assert!(state.grid.num_wrapped < wrapped);
+    assert_eq!(state.robot(0).position, Point2D::new(0, 0));
+    assert_eq!(state.robot(1).orientation, crate::robot::Orientation::Right);
+    assert_eq!(
+        state.can_apply(0, Action::Attach(Point2D::new(2, 0))),
+        Err(ActionError::NoBoosterInInventory(BoosterType::B))
+    );
+    assert_eq!(state.boosters.get(&Point2D::new(0, 1)), Some(&BoosterType::B));
+
+    assert_eq!(state.unapply_tick(clone), vec![Some(Action::Clone)]);
+    assert_eq!(state.agents_count(), 1);
+    assert_eq!(state.can_apply(0, Action::Clone), Ok(()));
+    assert_eq!(state.tick(), 4);
+
+    // wrapping the middle column cuts the free cells in two, undone with the tick
+    let mut state = Problem::parse("(0,0),(3,0),(3,3),(0,3)#(1,2)##").unwrap();
+    assert_eq!(state.regions().count(), 1);
+    let reverse = state.apply_tick(&[Some(Action::TurnCW)]).unwrap();
+    assert!(reverse.reverse_actions[0].as_ref().unwrap().split_region);
+    assert_eq!(state.regions().count(), 2);
+    state.unapply_tick(reverse);
+    assert_eq!(state.regions().count(), 1);
+    assert_eq!(state.regions().size(0), state.grid.num_free);
+
+    // purchased boosters are usable right away
+    let state = Problem::parse_with_purchases("(0,0),(4,0),(4,3),(0,3)#(0,0)##", &[BoosterType::B, BoosterType::B]).unwrap();
+    assert_eq!(state.inventory().count(BoosterType::B), 2);
+    assert_eq!(state.can_apply(0, Action::Attach(Point2D::new(2, 0))), Ok(()));
+}
+
+#[test]
+fn test_action_errors() {
+    use crate::core::State as _;
+    use crate::problem::Problem;
+
+    let mut state = Problem::parse("(0,0),(4,0),(4,3),(0,3)#(0,1)#(1,2),(2,2),(2,3),(1,3)#X(0,0);C(0,2);B(1,1);R(2,1);R(3,1)").unwrap();
+    assert_eq!(state.can_apply(0, Action::MoveLeft), Err(ActionError::OutOfBounds));
+    assert_eq!(state.can_apply(0, Action::Teleport(Point2D::new(0, 0))), Err(ActionError::UnknownBeacon));
+    assert_eq!(state.apply(0, Action::InstallBeacon).err(), Some(ActionError::NoBoosterInInventory(BoosterType::R)));
+    state.apply_tick(&[Some(Action::MoveUp)]).unwrap();
+    assert_eq!(state.can_apply(0, Action::MoveRight), Err(ActionError::HitObstacle));
+    assert_eq!(state.apply_tick(&[Some(Action::MoveUp)]).err(), Some((0, ActionError::OutOfBounds)));
+    state.apply_tick(&[Some(Action::MoveDown)]).unwrap();
+    state.apply_tick(&[Some(Action::MoveRight)]).unwrap();
+    assert_eq!(state.can_apply(0, Action::Attach(Point2D::new(1, 0))), Err(ActionError::AttachOccupied));
+    assert_eq!(state.can_apply(0, Action::Attach(Point2D::new(0, 0))), Err(ActionError::AttachOccupied));
+    assert_eq!(state.can_apply(0, Action::Attach(Point2D::new(3, 0))), Err(ActionError::AttachNotAdjacent));
+    assert_eq!(state.can_apply(0, Action::Attach(Point2D::new(2, 0))), Ok(()));
+    assert_eq!(state.can_apply(0, Action::Clone), Err(ActionError::NotOnSpawnPoint));
+    state.apply_tick(&[Some(Action::MoveRight)]).unwrap();
+    state.apply_tick(&[Some(Action::MoveRight)]).unwrap();
+    state.apply_tick(&[Some(Action::InstallBeacon)]).unwrap();
+    assert_eq!(state.can_apply(0, Action::Teleport(Point2D::new(3, 1))), Ok(()));
+    assert_eq!(state.can_apply(0, Action::Teleport(Point2D::new(2, 1))), Err(ActionError::UnknownBeacon));
+    assert_eq!(state.can_apply(0, Action::InstallBeacon), Err(ActionError::BeaconTooClose));
+    state.apply_tick(&[Some(Action::MoveLeft)]).unwrap();
+    assert_eq!(state.can_apply(0, Action::InstallBeacon), Ok(()));
+    state.apply_tick(&[Some(Action::MoveRight)]).unwrap();
+    for _ in 0..3 {
+        state.apply_tick(&[Some(Action::MoveLeft)]).unwrap();
+    }
+    state.apply_tick(&[Some(Action::MoveDown)]).unwrap();
+    assert_eq!(state.can_apply(0, Action::InstallBeacon), Err(ActionError::BeaconOnSpawnPoint));
+    assert_eq!(state.can_apply(0, Action::Clone), Ok(()));
+    assert_eq!(state.can_apply(1, Action::MoveUp), Err(ActionError::UnknownRobot));
+    assert_eq!(state.apply(1, Action::NoOp).err(), Some(ActionError::UnknownRobot));
+}
+
+#[test]
+fn test_initial_pickup() {
+    use crate::core::State as _;
+    use crate::problem::Problem;
+
+    // the booster on the initial position is collected before tick 0, unlike a spawn point
+    let mut state = Problem::parse("(0,0),(4,0),(4,1),(0,1)#(0,0)##B(0,0);B(1,0)").unwrap();
+    assert_eq!(state.inventory().count(BoosterType::B), 1);
+    assert_eq!(state.boosters.get(&Point2D::new(0, 0)), None);
+    state.apply_tick(&[Some(Action::Attach(Point2D::new(1, 2)))]).unwrap();
+    state.apply_tick(&[Some(Action::MoveRight)]).unwrap();
+    assert_eq!(state.can_apply(0, Action::Attach(Point2D::new(1, -2))), Ok(()));
+    state.apply_tick(&[Some(Action::Attach(Point2D::new(1, -2)))]).unwrap();
+    assert_eq!(state.inventory().count(BoosterType::B), 0);
+    assert_eq!(state.tick(), 3);
+
+    let mut state = Problem::parse("(0,0),(4,0),(4,1),(0,1)#(0,0)##B(0,0)").unwrap();
+    let reverse = state.apply_tick(&[Some(Action::Attach(Point2D::new(1, 2)))]).unwrap();
+    state.unapply_tick(reverse);
+    assert_eq!(state.inventory().count(BoosterType::B), 1);
+
+    let state = Problem::parse("(0,0),(4,0),(4,1),(0,1)#(0,0)##X(0,0)").unwrap();
+    assert_eq!(state.boosters.get(&Point2D::new(0, 0)), Some(&BoosterType::X));
+    assert_eq!(state.can_apply(0, Action::Clone), Err(ActionError::NoBoosterInInventory(BoosterType::C)));
+}
+
+#[test]
+fn test_fast_wheels_pickup() {
+    use crate::core::State as _;
+    use crate::problem::Problem;
+    use crate::solution::Solution;
+    use crate::validator;
+
+    let desc = |boosters: &str| format!("(0,0),(6,0),(6,2),(0,2)#(0,0)##C(1,0);X(0,0);F(0,1);{}", boosters);
+    let solution = Solution::parse("DACWFDDZ#ZZZB(1,2)ZZ").unwrap();
+    // robot 0 passes over B(3,1) on its way to (4,1) and the clone attaches it in the same tick
+    assert_eq!(validator::validate(&mut Problem::parse(&desc("B(3,1)")).unwrap(), &solution), Ok(9));
+    let error = validator::validate(&mut Problem::parse(&desc("B(4,1)")).unwrap(), &solution).unwrap_err();
+    assert_eq!((error.robot, error.time, error.action), (1, 6, Some(Action::Attach(Point2D::new(1, 2)))));
+
+    let mut state = Problem::parse(&desc("B(3,1)")).unwrap();
+    for action in [Action::MoveUp, Action::Accelerate, Action::MoveRight].iter() {
+        state.apply_tick(&[Some(*action)]).unwrap();
+    }
+    let reverse = state.apply_tick(&[Some(Action::MoveRight)]).unwrap();
+    assert_eq!(state.robot(0).position, Point2D::new(4, 1));
+    assert_eq!(state.inventory().count(BoosterType::B), 1);
+    state.unapply_tick(reverse);
+    assert_eq!(state.inventory().count(BoosterType::B), 0);
+    assert_eq!(state.boosters.get(&Point2D::new(3, 1)), Some(&BoosterType::B));
 }
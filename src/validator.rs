@@ -0,0 +1,138 @@
+use std::cmp;
+use std::fmt;
+
+use crate::core::State as _;
+use crate::state::{Action, State};
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct ValidationError {
+    pub robot: u32,
+    pub time: u32,
+    pub action: Option<Action>,
+    pub reason: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.action {
+            Some(action) => write!(f, "robot {} at tick {} ({}): {}", self.robot, self.time, action, self.reason),
+            None => write!(f, "at tick {}: {}", self.time, self.reason),
+        }
+    }
+}
+
+/// Replays a solution with the contest semantics and returns the number of
+/// time units it took.
+///
+/// Every tick each robot that exists at the start of the tick performs its
+/// next action, in id order. A clone consumes its first action on the tick
+/// after the one it was created on.
+pub fn validate(state: &mut State, solution: &[Vec<Action>]) -> Result<u32, ValidationError> {
+    let mut next_action = vec![0; solution.len()];
+    let mut time = 0;
+    loop {
+        let robots = cmp::min(state.agents_count() as usize, solution.len());
+        let actions = (0..robots).map(|id| solution[id].get(next_action[id]).cloned()).collect::<Vec<_>>();
+        if actions.iter().all(|a| a.is_none()) {
+            break;
+        }
+        if let Err((robot, error)) = state.apply_tick(&actions) {
+            return Err(ValidationError {
+                robot,
+                time,
+                action: actions[robot as usize],
+                reason: error.to_string(),
+            });
+        }
+        for (id, action) in actions.iter().enumerate() {
+            if action.is_some() {
+                next_action[id] += 1;
+            }
+        }
+        time += 1;
+    }
+
+    if solution.len() > state.agents_count() as usize && solution[state.agents_count() as usize..].iter().any(|a| !a.is_empty()) {
+        return Err(ValidationError {
+            robot: state.agents_count(),
+            time,
+            action: None,
+            reason: "actions given for a robot that was never cloned".to_string(),
+        });
+    }
+    if !state.complete() {
+        return Err(ValidationError {
+            robot: 0,
+            time,
+            action: None,
+            reason: "failed to cover the full task".to_string(),
+        });
+    }
+    Ok(time)
+}
+
+#[test]
+fn test_validate() {
+    use crate::problem::Problem;
+    use crate::solution::Solution;
+
+    let check = |desc: &str, sol: &str| {
+        validate(&mut Problem::parse(desc).unwrap(), &Solution::parse(sol).unwrap())
+    };
+    assert_eq!(check(include_str!("../part-1-initial/prob-001.desc"), include_str!("../part-1-initial/prob-001.sol")), Ok(13));
+    assert_eq!(check(include_str!("../part-1-initial/prob-001.desc"), "WWDDSSDDWWDS").unwrap_err().action, None);
+    assert_eq!(
+        check(include_str!("../part-1-initial/prob-001.desc"), "WWDDSSS"),
+        Err(ValidationError {
+            robot: 0,
+            time: 6,
+            action: Some(Action::MoveDown),
+            reason: "moving outside of the map".to_string(),
+        })
+    );
+    assert_eq!(check(include_str!("../part-1-initial/prob-030.desc"), include_str!("../part-1-initial/prob-030.sol")), Ok(706));
+    assert_eq!(check(include_str!("../part-3-clones/prob-230.desc"), include_str!("../part-3-clones/prob-230.sol")), Ok(1120));
+    // a booster on the initial position is usable at tick 0, as with the official checker
+    let desc = "(0,0),(5,0),(5,1),(0,1)#(0,0)##B(0,0)";
+    assert_eq!(check(desc, "B(1,2)DDD"), Ok(4));
+    assert_eq!(check(desc, "B(1,2)B(-1,0)DDD").unwrap_err().reason, "no B booster available");
+}
+
+#[test]
+fn test_all_solutions() {
+    use crate::json::Json;
+    use crate::problem::Problem;
+    use crate::solution::Solution;
+
+    // the .meta files of these predate the fixes to the clone and booster timing
+    let stale = ["prob-226", "prob-238", "prob-240"];
+    let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut mismatches = vec![];
+    let mut times = std::collections::HashMap::new();
+    for dir in &["part-1-initial", "part-2-teleports", "part-3-clones"] {
+        for entry in std::fs::read_dir(root.join(dir)).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "desc") {
+                continue;
+            }
+            let read = |extension: &str| std::fs::read_to_string(path.with_extension(extension)).unwrap();
+            let meta = Json::parse(&read("meta")).unwrap();
+            let best_time = meta.get("bestTime").and_then(|t| t.as_u32()).unwrap();
+            let mut state = Problem::parse(&read("desc")).unwrap();
+            let mut solution = Solution::parse(&read("sol")).unwrap();
+            Solution::translate(&mut solution, -state.origin());
+            let name = path.file_stem().unwrap().to_str().unwrap().to_string();
+            let time = validate(&mut state, &solution).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            if time != best_time {
+                mismatches.push(name.clone());
+            }
+            times.insert(name, time);
+        }
+    }
+    mismatches.sort();
+    assert_eq!(mismatches, stale);
+    // these use boosters picked up earlier in the tick on the first cell of a fast-wheels move
+    for (name, time) in [("prob-227", 859), ("prob-243", 1709), ("prob-274", 6558), ("prob-278", 7426), ("prob-292", 8516)].iter() {
+        assert_eq!(times[*name], *time, "{}", name);
+    }
+}